@@ -0,0 +1,308 @@
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::function::Function;
+use crate::opcode::OpCode;
+
+pub use serialize::LoadError;
+
+mod serialize;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Chunk {
+    code: Vec<u8>,
+    constants: Vec<Constant>,
+    lines: LineTable,
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        Default::default()
+    }
+
+    /// Encodes `op`, which was compiled from source `line`, at the end of the chunk and returns
+    /// its offset.
+    pub fn push_op(&mut self, op: OpCode, line: u32) -> usize {
+        let offset = self.code.len();
+        op.encode(&mut self.code);
+        for _ in offset..self.code.len() {
+            self.lines.push(line);
+        }
+        offset
+    }
+
+    /// Adds `constant` to the constant pool and returns its index, the compiler is responsible
+    /// for keeping the pool within the bounds addressable by `OpCode::ConstantLong`.
+    pub fn push_constant(&mut self, constant: Constant) -> u32 {
+        self.constants.push(constant);
+        (self.constants.len() - 1) as u32
+    }
+
+    /// Replaces the placeholder offset of the jump at byte `offset` with `jump`.
+    pub fn patch_jump(&mut self, offset: usize, jump: u16) {
+        let op = match self.decode_at(offset).0 {
+            OpCode::Jump(_) => OpCode::Jump(jump),
+            OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(jump),
+            OpCode::JumpIfTrue(_) => OpCode::JumpIfTrue(jump),
+            op => panic!("attempted to patch non-jump instruction {:?}", op),
+        };
+
+        let mut patched = Vec::with_capacity(op.encoded_len());
+        op.encode(&mut patched);
+        self.code[offset..offset + patched.len()].copy_from_slice(&patched);
+    }
+
+    /// The encoded instructions, see `OpCode` for the format.
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Decodes the instruction at byte `offset`, returning it along with its encoded length.
+    ///
+    /// # Panics
+    ///
+    /// If `offset` isn't the start of a valid instruction.
+    pub fn decode_at(&self, offset: usize) -> (OpCode, usize) {
+        OpCode::decode(&self.code[offset..])
+            .unwrap_or_else(|| panic!("invalid instruction at offset {}", offset))
+    }
+
+    /// Returns an iterator over the decoded instructions and their offsets.
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions {
+            code: &self.code,
+            offset: 0,
+        }
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    /// Returns the source line of the instruction containing byte `offset`.
+    pub fn line(&self, offset: usize) -> u32 {
+        self.lines.get(offset)
+    }
+
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    pub fn disassemble(&self, name: &str) {
+        println!("== {} ==", name);
+        print!("{}", self);
+    }
+
+    /// Formats the instruction at `offset` the same way as it appears in the chunk's
+    /// disassembly.
+    pub fn disassemble_instruction(&self, offset: usize) -> InstructionDisplay<'_> {
+        InstructionDisplay {
+            chunk: self,
+            offset,
+        }
+    }
+}
+
+impl Display for Chunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (offset, _) in self.instructions() {
+            write!(f, "{}", self.disassemble_instruction(offset))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats the instruction at `offset` in `chunk` as a line of its disassembly, created with
+/// `Chunk::disassemble_instruction`.
+pub struct InstructionDisplay<'a> {
+    chunk: &'a Chunk,
+    offset: usize,
+}
+
+impl Display for InstructionDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let chunk = self.chunk;
+        let i = self.offset;
+        let (op, _) = chunk.decode_at(i);
+
+        let simple = |f: &mut std::fmt::Formatter<'_>, name: &str| writeln!(f, "{}", name);
+        let constant = |f: &mut std::fmt::Formatter<'_>, name: &str, i: u32| {
+            writeln!(f, "{:16}{:4} '{}'", name, i, chunk.constants[i as usize])
+        };
+        let byte =
+            |f: &mut std::fmt::Formatter<'_>, name: &str, b: u8| writeln!(f, "{:16}{:4}", name, b);
+        let invoke = |f: &mut std::fmt::Formatter<'_>, name: &str, c: u32, args: u8| {
+            writeln!(
+                f,
+                "{:16} ({} args) {:4} '{}'",
+                name, args, c, chunk.constants[c as usize]
+            )
+        };
+        let local_constant = |f: &mut std::fmt::Formatter<'_>, name: &str, slot: u8, c: u8| {
+            writeln!(
+                f,
+                "{:16}{:4}{:4} '{}'",
+                name, slot, c, chunk.constants[c as usize]
+            )
+        };
+        let jump = |f: &mut std::fmt::Formatter<'_>, name: &str, from: usize, to: usize| {
+            writeln!(f, "{:16}{:4} -> {}", name, from, to)
+        };
+
+        write!(f, "{:04} ", i)?;
+        if i > 0 && chunk.line(i) == chunk.line(i - 1) {
+            write!(f, "   | ")?;
+        } else {
+            write!(f, "{:4} ", chunk.line(i))?;
+        }
+
+        let next = i + op.encoded_len();
+        match op {
+            OpCode::Return => simple(f, "OP_RETURN"),
+            OpCode::Constant(c) => constant(f, "OP_CONSTANT", c.into()),
+            OpCode::ConstantLong(c) => constant(f, "OP_CONSTANT_LONG", c),
+            OpCode::Nil => simple(f, "OP_NIL"),
+            OpCode::True => simple(f, "OP_TRUE"),
+            OpCode::False => simple(f, "OP_FALSE"),
+            OpCode::Pop => simple(f, "OP_POP"),
+            OpCode::GetLocal(slot) => byte(f, "OP_GET_LOCAL", slot),
+            OpCode::SetLocal(slot) => byte(f, "OP_SET_LOCAL", slot),
+            OpCode::GetGlobal(c) => constant(f, "OP_GET_GLOBAL", c),
+            OpCode::DefineGlobal(c) => constant(f, "OP_DEFINE_GLOBAL", c),
+            OpCode::SetGlobal(c) => constant(f, "OP_SET_GLOBAL", c),
+            OpCode::GetUpvalue(slot) => byte(f, "OP_GET_UPVALUE", slot),
+            OpCode::SetUpvalue(slot) => byte(f, "OP_SET_UPVALUE", slot),
+            OpCode::GetProperty(c) => constant(f, "OP_GET_PROPERTY", c),
+            OpCode::SetProperty(c) => constant(f, "OP_SET_PROPERTY", c),
+            OpCode::GetSuper(c) => constant(f, "OP_GET_SUPER", c),
+            OpCode::Equal => simple(f, "OP_EQUAL"),
+            OpCode::Greater => simple(f, "OP_GREATER"),
+            OpCode::Less => simple(f, "OP_LESS"),
+            OpCode::Add => simple(f, "OP_ADD"),
+            OpCode::Subtract => simple(f, "OP_SUBTRACT"),
+            OpCode::Multiply => simple(f, "OP_MULTIPLY"),
+            OpCode::Divide => simple(f, "OP_DIVIDE"),
+            OpCode::Not => simple(f, "OP_NOT"),
+            OpCode::Negate => simple(f, "OP_NEGATE"),
+            OpCode::Print => simple(f, "OP_PRINT"),
+            OpCode::Jump(offset) => jump(f, "OP_JUMP", i, next + offset as usize),
+            OpCode::JumpIfFalse(offset) => jump(f, "OP_JUMP_IF_FALSE", i, next + offset as usize),
+            OpCode::JumpIfTrue(offset) => jump(f, "OP_JUMP_IF_TRUE", i, next + offset as usize),
+            OpCode::Loop(offset) => jump(f, "OP_LOOP", i, next - offset as usize),
+            OpCode::Call(args) => byte(f, "OP_CALL", args),
+            OpCode::Closure(c) => {
+                constant(f, "OP_CLOSURE", c)?;
+                if let Constant::Function(function) = &chunk.constants[c as usize] {
+                    for upvalue in function.upvalues.iter() {
+                        writeln!(
+                            f,
+                            "{:04}    |      |                     {} {}",
+                            i,
+                            if upvalue.is_local { "local" } else { "upvalue" },
+                            upvalue.index
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+            OpCode::CloseUpvalue => simple(f, "OP_CLOSE_UPVALUE"),
+            OpCode::Class(c) => constant(f, "OP_CLASS", c),
+            OpCode::Inherit => simple(f, "OP_INHERIT"),
+            OpCode::Method(c) => constant(f, "OP_METHOD", c),
+            OpCode::Invoke(c, args) => invoke(f, "OP_INVOKE", c, args),
+            OpCode::SuperInvoke(c, args) => invoke(f, "OP_SUPER_INVOKE", c, args),
+            OpCode::AddLocalConstant(slot, c) => local_constant(f, "OP_ADD_LOCAL_CONST", slot, c),
+            OpCode::SubtractLocalConstant(slot, c) => {
+                local_constant(f, "OP_SUB_LOCAL_CONST", slot, c)
+            }
+            OpCode::LessLocalConstant(slot, c) => local_constant(f, "OP_LESS_LOCAL_CONST", slot, c),
+        }
+    }
+}
+
+/// Iterator over the instructions of a `Chunk` yielding each instruction along with its offset.
+///
+/// # Panics
+///
+/// If the chunk contains an invalid instruction.
+pub struct Instructions<'a> {
+    code: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = (usize, OpCode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.code.len() {
+            return None;
+        }
+
+        let offset = self.offset;
+        let (op, len) = OpCode::decode(&self.code[offset..])
+            .unwrap_or_else(|| panic!("invalid instruction at offset {}", offset));
+        self.offset += len;
+
+        Some((offset, op))
+    }
+}
+
+/// Run-length encoded source line numbers of each byte of code in a chunk, consecutive
+/// instructions are usually compiled from the same line.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct LineTable {
+    runs: Vec<LineRun>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct LineRun {
+    line: u32,
+    count: u32,
+}
+
+impl LineTable {
+    fn push(&mut self, line: u32) {
+        match self.runs.last_mut() {
+            Some(run) if run.line == line => run.count += 1,
+            _ => self.runs.push(LineRun { line, count: 1 }),
+        }
+    }
+
+    /// Returns the line of the byte at `index`, lookups are linear in the number of runs
+    /// as they are only needed when reporting errors or disassembling.
+    fn get(&self, index: usize) -> u32 {
+        let mut end = 0;
+        for run in self.runs.iter() {
+            end += run.count as usize;
+            if index < end {
+                return run.line;
+            }
+        }
+
+        panic!("no line recorded for offset {}", index)
+    }
+}
+
+/// A value known at compile time which is stored in a chunk's constant pool, the VM converts
+/// these into runtime values when a script is loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constant::Number(n) => write!(f, "{}", n),
+            Constant::String(s) => write!(f, "{}", s),
+            Constant::Function(function) => write!(f, "{}", function),
+        }
+    }
+}
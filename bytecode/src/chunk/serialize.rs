@@ -0,0 +1,387 @@
+//! The binary `.loxc` format for compiled chunks.
+//!
+//! A file consists of a header followed by the payload, all integers are little endian:
+//!
+//! ```text
+//! file     := "LOXC" version:u16 checksum:u32 chunk
+//! chunk    := code_len:u32 code:[u8] runs:u32 (line:u32 count:u32)* constants:u32 constant*
+//! constant := 0 number:f64 | 1 string | 2 function
+//! function := name:string arity:u8 upvalues:u32 (index:u8 is_local:u8)* chunk
+//! string   := len:u32 utf8:[u8]
+//! ```
+//!
+//! The checksum is the CRC-32 of the payload.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use lox_syntax::diagnostic::Diagnostic;
+
+use super::{Chunk, Constant, LineRun, LineTable};
+use crate::function::{Function, UpvalueDescriptor};
+use crate::verifier::VerifyError;
+
+const MAGIC: &[u8; 4] = b"LOXC";
+const FORMAT_VERSION: u16 = 4;
+
+const NUMBER: u8 = 0;
+const STRING: u8 = 1;
+const FUNCTION: u8 = 2;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    NotBytecode,
+    UnsupportedVersion { version: u16 },
+    ChecksumMismatch,
+    Malformed { message: Cow<'static, str> },
+    Invalid(VerifyError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::NotBytecode => f.write_str("not a compiled lox file"),
+            LoadError::UnsupportedVersion { version } => write!(
+                f,
+                "unsupported bytecode version {}, expected {}",
+                version, FORMAT_VERSION
+            ),
+            LoadError::ChecksumMismatch => f.write_str("checksum mismatch, the file is corrupt"),
+            LoadError::Malformed { message } => write!(f, "malformed bytecode: {}", message),
+            LoadError::Invalid(e) => write!(f, "invalid bytecode: {}", e),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// A script which fails verification is reported with the same code as invalid bytecode found
+/// while it runs.
+impl From<&LoadError> for Diagnostic {
+    fn from(error: &LoadError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string());
+        match error {
+            LoadError::Invalid(_) => diagnostic.with_code("E0306"),
+            LoadError::UnsupportedVersion { .. } => {
+                diagnostic.with_help("recompile the script with this version of rlox")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+fn malformed(message: impl Into<Cow<'static, str>>) -> LoadError {
+    LoadError::Malformed {
+        message: message.into(),
+    }
+}
+
+impl Chunk {
+    /// Writes the chunk, along with the prototypes of any functions in its constant pool, to
+    /// `writer` in the `.loxc` format.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut payload = Vec::new();
+        write_chunk(&mut payload, self);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&crc32(&payload).to_le_bytes())?;
+        writer.write_all(&payload)
+    }
+
+    /// Reads a chunk in the `.loxc` format from `reader`, checking the header and checksum.
+    ///
+    /// Only the structure of the file is checked, the instructions themselves are verified by
+    /// `loader::load`.
+    pub fn read_from(reader: &mut impl Read) -> Result<Chunk, LoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let mut input = Input { bytes: &bytes };
+        if input.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(LoadError::NotBytecode);
+        }
+
+        let version = input.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion { version });
+        }
+
+        let checksum = input.u32()?;
+        if crc32(input.bytes) != checksum {
+            return Err(LoadError::ChecksumMismatch);
+        }
+
+        let chunk = read_chunk(&mut input)?;
+        if !input.bytes.is_empty() {
+            return Err(malformed("trailing bytes after chunk"));
+        }
+
+        Ok(chunk)
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk: &Chunk) {
+    write_len(out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+
+    write_len(out, chunk.lines.runs.len());
+    for run in chunk.lines.runs.iter() {
+        out.extend(run.line.to_le_bytes());
+        out.extend(run.count.to_le_bytes());
+    }
+
+    write_len(out, chunk.constants.len());
+    for constant in chunk.constants.iter() {
+        match constant {
+            Constant::Number(n) => {
+                out.push(NUMBER);
+                out.extend(n.to_bits().to_le_bytes());
+            }
+            Constant::String(s) => {
+                out.push(STRING);
+                write_str(out, s);
+            }
+            Constant::Function(function) => {
+                out.push(FUNCTION);
+                write_function(out, function);
+            }
+        }
+    }
+}
+
+fn write_function(out: &mut Vec<u8>, function: &Function) {
+    write_str(out, &function.name);
+    out.push(function.arity);
+    write_len(out, function.upvalues.len());
+    for upvalue in function.upvalues.iter() {
+        out.push(upvalue.index);
+        out.push(upvalue.is_local as u8);
+    }
+    write_chunk(out, &function.chunk);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_len(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    let len = u32::try_from(len).expect("length exceeds the limits of the bytecode format");
+    out.extend(len.to_le_bytes());
+}
+
+fn read_chunk(input: &mut Input) -> Result<Chunk, LoadError> {
+    let len = input.len()?;
+    let code = input.take(len)?.to_vec();
+
+    let mut lines = LineTable::default();
+    let mut covered = 0usize;
+    for _ in 0..input.len()? {
+        let line = input.u32()?;
+        let count = input.u32()?;
+        covered += count as usize;
+        lines.runs.push(LineRun { line, count });
+    }
+    if covered != code.len() {
+        return Err(malformed("line table doesn't cover the chunk's code"));
+    }
+
+    let mut constants = Vec::new();
+    for _ in 0..input.len()? {
+        let constant = match input.u8()? {
+            NUMBER => Constant::Number(f64::from_bits(input.u64()?)),
+            STRING => Constant::String(input.str()?.into()),
+            FUNCTION => Constant::Function(Rc::new(read_function(input)?)),
+            tag => return Err(malformed(format!("unknown constant tag {}", tag))),
+        };
+        constants.push(constant);
+    }
+
+    Ok(Chunk {
+        code,
+        constants,
+        lines,
+    })
+}
+
+fn read_function(input: &mut Input) -> Result<Function, LoadError> {
+    let name = input.str()?.to_owned();
+    let arity = input.u8()?;
+
+    let mut upvalues = Vec::new();
+    for _ in 0..input.len()? {
+        let index = input.u8()?;
+        let is_local = match input.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(malformed("invalid upvalue descriptor")),
+        };
+        upvalues.push(UpvalueDescriptor { index, is_local });
+    }
+
+    let chunk = read_chunk(input)?;
+
+    Ok(Function {
+        name,
+        arity,
+        upvalues,
+        chunk,
+    })
+}
+
+/// The unread remainder of a file.
+struct Input<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], LoadError> {
+        if n > self.bytes.len() {
+            return Err(malformed("unexpected end of file"));
+        }
+
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn len(&mut self) -> Result<usize, LoadError> {
+        self.u32().map(|len| len as usize)
+    }
+
+    fn str(&mut self) -> Result<&'a str, LoadError> {
+        let len = self.len()?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| malformed("string is not valid UTF-8"))
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use lox_syntax::Parser;
+
+    use super::*;
+    use crate::compiler::Compiler;
+
+    fn compile(source: &str) -> Function {
+        let mut parser = Parser::new(source);
+        let statements = parser.parse();
+        assert!(parser.diagnostics().is_empty());
+
+        let mut compiler = Compiler::new(source);
+        let function = compiler.compile(&statements);
+        assert!(compiler.diagnostics().is_empty());
+        function
+    }
+
+    fn serialize(chunk: &Chunk) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        chunk.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn computes_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trips_examples() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../example");
+        for entry in fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+            let function = compile(&source);
+
+            let bytes = serialize(&function.chunk);
+            let chunk = Chunk::read_from(&mut bytes.as_slice()).unwrap();
+            assert_eq!(
+                chunk,
+                function.chunk,
+                "{} didn't round trip",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_wide_operands() {
+        let source: String = (0..300).map(|i| format!("var a{} = {};", i, i)).collect();
+        let function = compile(&(source + "class A { m() {} } A().m(); print a299;"));
+
+        let bytes = serialize(&function.chunk);
+        let chunk = Chunk::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(chunk, function.chunk);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let bytes = serialize(&compile("fun f(a) { return a + 1; } print f(1);").chunk);
+        let read = |bytes: &[u8]| Chunk::read_from(&mut &*bytes);
+
+        assert!(matches!(read(b"#!lox"), Err(LoadError::NotBytecode)));
+
+        let mut version = bytes.clone();
+        version[4] = 0xff;
+        assert!(matches!(
+            read(&version),
+            Err(LoadError::UnsupportedVersion { .. })
+        ));
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(read(&corrupt), Err(LoadError::ChecksumMismatch)));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(read(truncated).is_err());
+    }
+}
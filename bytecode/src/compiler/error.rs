@@ -0,0 +1,155 @@
+use std::{
+    borrow::Cow,
+    fmt::{Display, Formatter},
+};
+
+use lox_syntax::diagnostic::Diagnostic;
+use lox_syntax::span::Span;
+
+#[derive(Debug)]
+pub enum CompileError {
+    InitializeFromSelf {
+        span: Span,
+    },
+    AlreadyDeclared {
+        span: Span,
+    },
+    ReturnOutsideFn {
+        span: Span,
+    },
+    ThisOutsideClass {
+        span: Span,
+    },
+    ReturnValueFromInit {
+        span: Span,
+    },
+    InheritFromSelf {
+        span: Span,
+    },
+    InvalidSuper {
+        span: Span,
+        message: Cow<'static, str>,
+    },
+    OutsideLoop {
+        span: Span,
+        message: Cow<'static, str>,
+    },
+    TooManyLocals {
+        span: Span,
+    },
+    TooManyUpvalues {
+        span: Span,
+    },
+    TooManyConstants {
+        span: Span,
+    },
+    TooManyParameters {
+        span: Span,
+    },
+    TooManyArguments {
+        span: Span,
+    },
+    JumpTooLarge {
+        span: Span,
+    },
+    /// The expression is only supported by the tree-walk interpreter.
+    Unsupported {
+        span: Span,
+        message: Cow<'static, str>,
+    },
+}
+
+impl CompileError {
+    pub fn span(&self) -> Span {
+        use CompileError::*;
+
+        match self {
+            InitializeFromSelf { span }
+            | AlreadyDeclared { span }
+            | ReturnOutsideFn { span }
+            | ThisOutsideClass { span }
+            | ReturnValueFromInit { span }
+            | InheritFromSelf { span }
+            | InvalidSuper { span, .. }
+            | OutsideLoop { span, .. }
+            | TooManyLocals { span }
+            | TooManyUpvalues { span }
+            | TooManyConstants { span }
+            | TooManyParameters { span }
+            | TooManyArguments { span }
+            | JumpTooLarge { span }
+            | Unsupported { span, .. } => *span,
+        }
+    }
+
+    /// The stable code identifying the kind of error, shared with the equivalent errors reported
+    /// by the tree-walk resolver.
+    pub fn code(&self) -> &'static str {
+        use CompileError::*;
+
+        match self {
+            InitializeFromSelf { .. } => "E0101",
+            AlreadyDeclared { .. } => "E0102",
+            ReturnOutsideFn { .. } => "E0104",
+            ThisOutsideClass { .. } => "E0105",
+            ReturnValueFromInit { .. } => "E0106",
+            InheritFromSelf { .. } => "E0107",
+            InvalidSuper { .. } => "E0108",
+            OutsideLoop { .. } => "E0109",
+            TooManyLocals { .. } => "E0201",
+            TooManyUpvalues { .. } => "E0202",
+            TooManyConstants { .. } => "E0203",
+            TooManyParameters { .. } => "E0204",
+            TooManyArguments { .. } => "E0205",
+            JumpTooLarge { .. } => "E0206",
+            Unsupported { .. } => "E0207",
+        }
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use CompileError::*;
+
+        match self {
+            InitializeFromSelf { .. } => f.write_str("defined variable from self in declaration"),
+            AlreadyDeclared { .. } => f.write_str("variable has already been declared"),
+            ReturnOutsideFn { .. } => f.write_str("can't return outside of function body"),
+            ThisOutsideClass { .. } => f.write_str("can't use `this` outside of a class"),
+            ReturnValueFromInit { .. } => f.write_str("can't return a value inside `init` method"),
+            InheritFromSelf { .. } => f.write_str("a class can't inherit from itself"),
+            InvalidSuper { message, .. } => f.write_str(message),
+            OutsideLoop { message, .. } => f.write_str(message),
+            TooManyLocals { .. } => f.write_str("too many local variables in function"),
+            TooManyUpvalues { .. } => f.write_str("too many closure variables in function"),
+            TooManyConstants { .. } => f.write_str("too many constants in one chunk"),
+            TooManyParameters { .. } => f.write_str("can't have more than 255 parameters"),
+            TooManyArguments { .. } => f.write_str("can't have more than 255 arguments"),
+            JumpTooLarge { .. } => f.write_str("too much code to jump over"),
+            Unsupported { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string())
+            .with_code(error.code())
+            .with_span(error.span());
+
+        match error {
+            CompileError::InitializeFromSelf { .. } => diagnostic
+                .with_note("a local variable can't be read in its own initializer")
+                .with_help("give the variable a different name to read the outer variable"),
+            CompileError::ReturnValueFromInit { .. } => {
+                diagnostic.with_note("initializers always return the instance being initialized")
+            }
+            CompileError::JumpTooLarge { .. } => {
+                diagnostic.with_help("move some of the code into a function")
+            }
+            CompileError::Unsupported { .. } => diagnostic
+                .with_help("run the script with the tree-walk interpreter (`--tree-walk`)"),
+            _ => diagnostic,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use lox_syntax::ast::expr::{
+    Assign, BinOp, Binary, Call, Expr, Get, Literal, Logical, LogicalOp, Set, Super, This, UnOp,
+    Unary, Value as LiteralValue,
+};
+
+use crate::chunk::Constant;
+use crate::opcode::OpCode;
+
+use super::{CompileError, Compiler, FunctionType};
+
+impl Compiler {
+    pub(super) fn compile_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(l) => self.compile_literal(l),
+            Expr::Var(v) => self.get_variable(&v.id.name, v.span),
+            Expr::Unary(u) => self.compile_unary_expr(u),
+            Expr::Binary(b) => self.compile_binary_expr(b),
+            Expr::Logical(l) => self.compile_logical_expr(l),
+            Expr::Grouping(g) => self.compile_expr(&g.expr),
+            Expr::Assign(a) => self.compile_assign_expr(a),
+            Expr::Call(c) => self.compile_call_expr(c),
+            Expr::Get(g) => self.compile_get_expr(g),
+            Expr::Set(s) => self.compile_set_expr(s),
+            Expr::This(t) => self.compile_this_expr(t),
+            Expr::Super(s) => self.compile_super_expr(s),
+            Expr::Lambda(l) => self.compile_function(&l.decl, FunctionType::Function),
+            Expr::List(_) | Expr::Index(_) | Expr::SetIndex(_) => {
+                self.error(CompileError::Unsupported {
+                    span: expr.span(),
+                    message: "lists aren't supported by the bytecode interpreter".into(),
+                })
+            }
+        }
+    }
+
+    fn compile_literal(&mut self, literal: &Literal) {
+        let op = match &literal.value {
+            LiteralValue::Nil => OpCode::Nil,
+            LiteralValue::Boolean(true) => OpCode::True,
+            LiteralValue::Boolean(false) => OpCode::False,
+            LiteralValue::Number(n) => {
+                return self.emit_constant(Constant::Number(*n), literal.span)
+            }
+            LiteralValue::String(s) => {
+                return self.emit_constant(Constant::String(s.as_str().into()), literal.span)
+            }
+        };
+
+        self.emit(op, literal.span);
+    }
+
+    fn compile_unary_expr(&mut self, unary: &Unary) {
+        self.compile_expr(&unary.expr);
+
+        match unary.op {
+            UnOp::Minus => self.emit(OpCode::Negate, unary.span),
+            UnOp::Bang => self.emit(OpCode::Not, unary.span),
+        };
+    }
+
+    fn compile_binary_expr(&mut self, binary: &Binary) {
+        use BinOp::*;
+
+        self.compile_expr(&binary.lhs);
+        self.compile_expr(&binary.rhs);
+
+        let ops: &[OpCode] = match binary.op {
+            Add => &[OpCode::Add],
+            Subtract => &[OpCode::Subtract],
+            Multiply => &[OpCode::Multiply],
+            Divide => &[OpCode::Divide],
+            Equal => &[OpCode::Equal],
+            NotEqual => &[OpCode::Equal, OpCode::Not],
+            Greater => &[OpCode::Greater],
+            GreaterEqual => &[OpCode::Less, OpCode::Not],
+            Less => &[OpCode::Less],
+            LessEqual => &[OpCode::Greater, OpCode::Not],
+        };
+
+        for &op in ops {
+            self.emit(op, binary.span);
+        }
+    }
+
+    fn compile_logical_expr(&mut self, logical: &Logical) {
+        self.compile_expr(&logical.lhs);
+
+        match logical.op {
+            LogicalOp::And => {
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse, logical.span);
+                self.emit(OpCode::Pop, logical.span);
+                self.compile_expr(&logical.rhs);
+                self.patch_jump(end_jump, logical.span);
+            }
+            LogicalOp::Or => {
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse, logical.span);
+                let end_jump = self.emit_jump(OpCode::Jump, logical.span);
+                self.patch_jump(else_jump, logical.span);
+                self.emit(OpCode::Pop, logical.span);
+                self.compile_expr(&logical.rhs);
+                self.patch_jump(end_jump, logical.span);
+            }
+        }
+    }
+
+    fn compile_assign_expr(&mut self, assign: &Assign) {
+        self.compile_expr(&assign.expr);
+        self.set_variable(&assign.var.name, assign.var.span);
+    }
+
+    fn compile_call_expr(&mut self, call: &Call) {
+        // Method calls are fused into a single instruction to avoid creating a bound method
+        match call.callee.as_ref() {
+            Expr::Get(get) => {
+                self.compile_expr(&get.object);
+                let arg_count = self.compile_args(call);
+                let name = self.identifier_constant(&get.property.name, get.property.span);
+                self.emit(OpCode::Invoke(name, arg_count), call.span);
+            }
+            Expr::Super(super_expr) => {
+                if !self.check_super(super_expr) {
+                    return;
+                }
+
+                let name =
+                    self.identifier_constant(&super_expr.method.name, super_expr.method.span);
+                self.get_variable("this", super_expr.span);
+                let arg_count = self.compile_args(call);
+                self.get_variable("super", super_expr.span);
+                self.emit(OpCode::SuperInvoke(name, arg_count), call.span);
+            }
+            _ => {
+                self.compile_expr(&call.callee);
+                let arg_count = self.compile_args(call);
+                self.emit(OpCode::Call(arg_count), call.span);
+            }
+        }
+    }
+
+    /// Compiles the arguments of `call` and returns how many there are.
+    fn compile_args(&mut self, call: &Call) -> u8 {
+        for arg in call.args.iter() {
+            self.compile_expr(arg);
+        }
+
+        u8::try_from(call.args.len()).unwrap_or_else(|_| {
+            self.error(CompileError::TooManyArguments { span: call.span });
+            u8::MAX
+        })
+    }
+
+    fn compile_get_expr(&mut self, get: &Get) {
+        self.compile_expr(&get.object);
+        let name = self.identifier_constant(&get.property.name, get.property.span);
+        self.emit(OpCode::GetProperty(name), get.span);
+    }
+
+    fn compile_set_expr(&mut self, set: &Set) {
+        self.compile_expr(&set.object);
+        self.compile_expr(&set.value);
+        let name = self.identifier_constant(&set.property.name, set.property.span);
+        self.emit(OpCode::SetProperty(name), set.span);
+    }
+
+    fn compile_this_expr(&mut self, this: &This) {
+        if self.classes.is_empty() {
+            self.error(CompileError::ThisOutsideClass { span: this.span });
+            return;
+        }
+
+        self.get_variable("this", this.span);
+    }
+
+    fn compile_super_expr(&mut self, super_expr: &Super) {
+        if !self.check_super(super_expr) {
+            return;
+        }
+
+        let name = self.identifier_constant(&super_expr.method.name, super_expr.method.span);
+        self.get_variable("this", super_expr.span);
+        self.get_variable("super", super_expr.span);
+        self.emit(OpCode::GetSuper(name), super_expr.span);
+    }
+
+    /// Reports an error if `super` can't be used where `super_expr` appears, returning whether it
+    /// can.
+    fn check_super(&mut self, super_expr: &Super) -> bool {
+        let message = match self.classes.last() {
+            None => "can't use 'super' outside of a class",
+            Some(class) if !class.has_super_class => {
+                "can't use 'super' in a class with no superclass"
+            }
+            _ => return true,
+        };
+
+        self.error(CompileError::InvalidSuper {
+            span: super_expr.span,
+            message: message.into(),
+        });
+        false
+    }
+}
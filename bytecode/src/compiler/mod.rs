@@ -0,0 +1,787 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use lox_syntax::ast::stmt::Stmt;
+use lox_syntax::span::Span;
+
+use crate::chunk::{Chunk, Constant};
+use crate::function::{Function, UpvalueDescriptor};
+use crate::opcode::OpCode;
+
+pub use error::CompileError;
+
+mod error;
+mod expr;
+mod peephole;
+mod stmt;
+
+/// Slots are addressed with a single byte operand.
+const MAX_LOCALS: usize = u8::MAX as usize + 1;
+const MAX_UPVALUES: usize = u8::MAX as usize + 1;
+/// Constants are loaded with at most a 24 bit operand by `OpCode::ConstantLong`.
+const MAX_CONSTANTS: usize = 1 << 24;
+
+/// Compiles the AST produced by `lox_syntax::Parser` into a tree of `Function`s.
+///
+/// Variable resolution is performed during compilation: locals are assigned stack slots, captured
+/// variables are resolved to upvalues and everything else is treated as a global. Semantic errors
+/// are collected into `diagnostics` rather than short-circuiting so that they can all be reported
+/// back to the user.
+///
+/// Each function's chunk is run through a peephole optimizer once it has been compiled, unless
+/// disabled with `set_peephole`.
+#[derive(Debug)]
+pub struct Compiler {
+    functions: Vec<FunctionState>,
+    classes: Vec<ClassState>,
+    diagnostics: Vec<CompileError>,
+    /// Byte offset of the start of each line of the source, used to map spans to line numbers.
+    line_starts: Vec<usize>,
+    peephole: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FunctionType {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug)]
+struct FunctionState {
+    function: Function,
+    function_type: FunctionType,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    /// The loops enclosing the statement being compiled, innermost last.
+    loops: Vec<LoopState>,
+    /// Indices of the number and string constants already in the function's chunk so that they
+    /// can be reused.
+    constants: HashMap<ConstantKey, u32>,
+}
+
+#[derive(Debug)]
+struct LoopState {
+    /// The scope depth outside of the loop body, locals deeper than this are discarded when
+    /// jumping out of the body.
+    scope_depth: usize,
+    /// Jumps emitted by `break` which are patched to the end of the loop.
+    breaks: Vec<usize>,
+    /// Jumps emitted by `continue` which are patched to the end of the body.
+    continues: Vec<usize>,
+}
+
+/// Identifies a constant for deduplication, numbers are compared by their bits so that `0` and
+/// `-0` remain distinct.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    String(Rc<str>),
+}
+
+#[derive(Debug)]
+struct Local {
+    name: String,
+    /// `None` while the local has been declared but its initializer has not yet been compiled.
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+#[derive(Debug)]
+struct ClassState {
+    has_super_class: bool,
+}
+
+impl FunctionState {
+    fn new(function: Function, function_type: FunctionType) -> Self {
+        // Slot 0 holds the callee, for methods this is the receiver which is accessed as `this`.
+        let slot_zero = match function_type {
+            FunctionType::Method | FunctionType::Initializer => "this",
+            FunctionType::Script | FunctionType::Function => "",
+        };
+
+        Self {
+            function,
+            function_type,
+            locals: vec![Local {
+                name: slot_zero.into(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            scope_depth: 0,
+            loops: Vec::new(),
+            constants: HashMap::new(),
+        }
+    }
+}
+
+impl Compiler {
+    /// Creates a compiler for the AST parsed from `source`, the source is needed to record the
+    /// line of each emitted instruction.
+    pub fn new(source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            functions: Vec::new(),
+            classes: Vec::new(),
+            diagnostics: Vec::new(),
+            line_starts,
+            peephole: true,
+        }
+    }
+
+    /// Enables or disables the peephole optimizer, it is enabled by default.
+    pub fn set_peephole(&mut self, enabled: bool) {
+        self.peephole = enabled;
+    }
+
+    pub fn compile(&mut self, stmts: &[Stmt]) -> Function {
+        self.functions.push(FunctionState::new(
+            Function::new("", 0),
+            FunctionType::Script,
+        ));
+
+        for stmt in stmts {
+            self.compile_stmt(stmt);
+        }
+
+        let end = stmts
+            .last()
+            .map_or_else(|| Span::new(0, 0), |stmt| end_of(stmt.span()));
+        self.end_function(end)
+    }
+
+    /// Reports the errors in `stmts` without keeping the compiled code. The AST should be
+    /// checked before it is optimized as errors in branches removed by the optimizer are
+    /// otherwise never found.
+    pub fn check(&mut self, stmts: &[Stmt]) {
+        self.compile(stmts);
+    }
+
+    pub fn diagnostics(&self) -> &[CompileError] {
+        &self.diagnostics
+    }
+
+    fn current(&self) -> &FunctionState {
+        self.functions
+            .last()
+            .expect("no function is being compiled")
+    }
+
+    fn current_mut(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("no function is being compiled")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current_mut().function.chunk
+    }
+
+    /// Emits `op`, attributing it to the line on which `span` starts.
+    fn emit(&mut self, op: OpCode, span: Span) -> usize {
+        let line = self.line(span);
+        self.chunk().push_op(op, line)
+    }
+
+    fn line(&self, span: Span) -> u32 {
+        let lo = span.range().start;
+        self.line_starts.partition_point(|&start| start <= lo) as u32
+    }
+
+    /// Adds `constant` to the current chunk, reusing an identical number or string constant if
+    /// one is already present.
+    fn make_constant(&mut self, constant: Constant, span: Span) -> u32 {
+        let key = match &constant {
+            Constant::Number(n) => Some(ConstantKey::Number(n.to_bits())),
+            Constant::String(s) => Some(ConstantKey::String(s.clone())),
+            Constant::Function(_) => None,
+        };
+
+        if let Some(&index) = key.as_ref().and_then(|k| self.current().constants.get(k)) {
+            return index;
+        }
+
+        if self.chunk().constants().len() >= MAX_CONSTANTS {
+            self.error(CompileError::TooManyConstants { span });
+            return 0;
+        }
+
+        let index = self.chunk().push_constant(constant);
+        if let Some(key) = key {
+            self.current_mut().constants.insert(key, index);
+        }
+        index
+    }
+
+    /// Emits the instruction to load `constant`, using the wide form if its index doesn't fit in
+    /// a byte.
+    fn emit_constant(&mut self, constant: Constant, span: Span) {
+        let index = self.make_constant(constant, span);
+        let op = match u8::try_from(index) {
+            Ok(index) => OpCode::Constant(index),
+            Err(_) => OpCode::ConstantLong(index),
+        };
+        self.emit(op, span);
+    }
+
+    fn identifier_constant(&mut self, name: &str, span: Span) -> u32 {
+        self.make_constant(Constant::String(name.into()), span)
+    }
+
+    /// Emits a jump with a placeholder offset which must later be filled in by `patch_jump`.
+    fn emit_jump(&mut self, op: fn(u16) -> OpCode, span: Span) -> usize {
+        self.emit(op(u16::MAX), span)
+    }
+
+    /// Points the jump at `index` to the next instruction to be emitted.
+    fn patch_jump(&mut self, index: usize, span: Span) {
+        let jump_end = index + OpCode::Jump(0).encoded_len();
+        let offset = self.chunk().len() - jump_end;
+        let offset = u16::try_from(offset).unwrap_or_else(|_| {
+            self.error(CompileError::JumpTooLarge { span });
+            u16::MAX
+        });
+
+        self.chunk().patch_jump(index, offset);
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, span: Span) {
+        let offset = self.chunk().len() + OpCode::Loop(0).encoded_len() - loop_start;
+        let offset = u16::try_from(offset).unwrap_or_else(|_| {
+            self.error(CompileError::JumpTooLarge { span });
+            u16::MAX
+        });
+
+        self.emit(OpCode::Loop(offset), span);
+    }
+
+    fn emit_return(&mut self, span: Span) {
+        if self.current().function_type == FunctionType::Initializer {
+            self.emit(OpCode::GetLocal(0), span);
+        } else {
+            self.emit(OpCode::Nil, span);
+        }
+        self.emit(OpCode::Return, span);
+    }
+
+    fn begin_function(&mut self, function: Function, function_type: FunctionType) {
+        self.functions
+            .push(FunctionState::new(function, function_type));
+    }
+
+    /// Finishes the current function, `end` is the span of the end of its body which the implicit
+    /// return is attributed to.
+    fn end_function(&mut self, end: Span) -> Function {
+        self.emit_return(end);
+        let mut function = self
+            .functions
+            .pop()
+            .expect("no function is being compiled")
+            .function;
+
+        // The jumps in a chunk with errors may not be valid
+        if self.peephole && self.diagnostics.is_empty() {
+            function.chunk = peephole::optimize(&function.chunk);
+        }
+        function
+    }
+
+    fn begin_scope(&mut self) {
+        self.current_mut().scope_depth += 1;
+    }
+
+    /// Ends the current scope, the instructions discarding its locals are attributed to `end`.
+    fn end_scope(&mut self, end: Span) {
+        let state = self.current_mut();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+
+        while let Some(local) = self.current().locals.last() {
+            if local.depth.is_none_or(|d| d <= depth) {
+                break;
+            }
+
+            let op = if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.emit(op, end);
+            self.current_mut().locals.pop();
+        }
+    }
+
+    /// Emits the instructions discarding the locals deeper than `depth` without ending their
+    /// scopes, used when jumping out of a scope.
+    fn discard_locals(&mut self, depth: usize, span: Span) {
+        let ops: Vec<_> = self
+            .current()
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|d| d > depth))
+            .map(|local| match local.is_captured {
+                true => OpCode::CloseUpvalue,
+                false => OpCode::Pop,
+            })
+            .collect();
+
+        for op in ops {
+            self.emit(op, span);
+        }
+    }
+
+    fn add_local(&mut self, name: &str, span: Span) {
+        if self.current().locals.len() >= MAX_LOCALS {
+            self.error(CompileError::TooManyLocals { span });
+            return;
+        }
+
+        self.current_mut().locals.push(Local {
+            name: name.into(),
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    /// Declares a local variable in the current scope, globals are late bound and are therefore
+    /// not declared.
+    fn declare_variable(&mut self, name: &str, span: Span) {
+        let state = self.current();
+        if state.scope_depth == 0 {
+            return;
+        }
+
+        let already_declared = state
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|d| d >= state.scope_depth))
+            .any(|local| local.name == name);
+
+        if already_declared {
+            self.error(CompileError::AlreadyDeclared { span });
+        }
+
+        self.add_local(name, span);
+    }
+
+    fn define_variable(&mut self, name: &str, span: Span) {
+        if self.current().scope_depth > 0 {
+            self.mark_initialized();
+        } else {
+            let constant = self.identifier_constant(name, span);
+            self.emit(OpCode::DefineGlobal(constant), span);
+        }
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.current_mut();
+        let depth = state.scope_depth;
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    /// Emits the instruction to push the variable `name` onto the stack.
+    fn get_variable(&mut self, name: &str, span: Span) {
+        let op = if let Some(slot) = self.resolve_local(self.functions.len() - 1, name, span) {
+            OpCode::GetLocal(slot)
+        } else if let Some(index) = self.resolve_upvalue(self.functions.len() - 1, name, span) {
+            OpCode::GetUpvalue(index)
+        } else {
+            OpCode::GetGlobal(self.identifier_constant(name, span))
+        };
+
+        self.emit(op, span);
+    }
+
+    /// Emits the instruction to assign the value on top of the stack to the variable `name`.
+    fn set_variable(&mut self, name: &str, span: Span) {
+        let op = if let Some(slot) = self.resolve_local(self.functions.len() - 1, name, span) {
+            OpCode::SetLocal(slot)
+        } else if let Some(index) = self.resolve_upvalue(self.functions.len() - 1, name, span) {
+            OpCode::SetUpvalue(index)
+        } else {
+            OpCode::SetGlobal(self.identifier_constant(name, span))
+        };
+
+        self.emit(op, span);
+    }
+
+    fn resolve_local(&mut self, level: usize, name: &str, span: Span) -> Option<u8> {
+        let (slot, local) = self.functions[level]
+            .locals
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, local)| local.name == name)?;
+
+        // If the variable is referenced before it has been defined but after it has been
+        // declared then it is being used in a situation such as var a = a;
+        if local.depth.is_none() {
+            self.error(CompileError::InitializeFromSelf { span });
+        }
+
+        Some(slot as u8)
+    }
+
+    fn resolve_upvalue(&mut self, level: usize, name: &str, span: Span) -> Option<u8> {
+        if level == 0 {
+            return None;
+        }
+
+        if let Some(slot) = self.resolve_local(level - 1, name, span) {
+            self.functions[level - 1].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(level, slot, true, span));
+        }
+
+        self.resolve_upvalue(level - 1, name, span)
+            .map(|index| self.add_upvalue(level, index, false, span))
+    }
+
+    fn add_upvalue(&mut self, level: usize, index: u8, is_local: bool, span: Span) -> u8 {
+        let upvalue = UpvalueDescriptor { index, is_local };
+        let upvalues = &mut self.functions[level].function.upvalues;
+
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return existing as u8;
+        }
+
+        if upvalues.len() >= MAX_UPVALUES {
+            self.error(CompileError::TooManyUpvalues { span });
+            return 0;
+        }
+
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u8
+    }
+
+    fn error(&mut self, error: CompileError) {
+        self.diagnostics.push(error);
+    }
+}
+
+/// Returns a span covering the last character of `span`.
+fn end_of(span: Span) -> Span {
+    let end = span.range().end;
+    Span::new(end.saturating_sub(1), end)
+}
+
+#[cfg(test)]
+mod tests {
+    use lox_syntax::optimize::optimize;
+    use lox_syntax::Parser;
+
+    use super::*;
+    use crate::chunk::Constant;
+    use OpCode::*;
+
+    fn compile(source: &str) -> (Function, Vec<CompileError>) {
+        let mut parser = Parser::new(source);
+        let statements = parser.parse();
+        assert!(parser.diagnostics().is_empty());
+
+        // The chunks are checked as they are emitted, see `peephole` for the optimized output
+        let mut compiler = Compiler::new(source);
+        compiler.set_peephole(false);
+        let function = compiler.compile(&statements);
+        (function, compiler.diagnostics)
+    }
+
+    fn ops(chunk: &Chunk) -> Vec<OpCode> {
+        chunk.instructions().map(|(_, op)| op).collect()
+    }
+
+    #[test]
+    fn compiles_arithmetic_expressions() {
+        let (function, diagnostics) = compile("print 1 + 2 * 3;");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            ops(&function.chunk),
+            [
+                Constant(0),
+                Constant(1),
+                Constant(2),
+                Multiply,
+                Add,
+                Print,
+                Nil,
+                Return
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_globals_and_locals() {
+        let (function, diagnostics) = compile("var a = 1; { var b = a; b = 2; }");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            ops(&function.chunk),
+            [
+                Constant(0),
+                DefineGlobal(1),
+                GetGlobal(1),
+                Constant(2),
+                SetLocal(1),
+                Pop,
+                Pop,
+                Nil,
+                Return
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_control_flow() {
+        let (function, diagnostics) = compile("while (true) { if (false) print 1; }");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            ops(&function.chunk),
+            [
+                True,
+                JumpIfFalse(16),
+                Pop,
+                False,
+                JumpIfFalse(7),
+                Pop,
+                Constant(0),
+                Print,
+                Jump(1),
+                Pop,
+                Loop(20),
+                Pop,
+                Nil,
+                Return
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_loop_control() {
+        let (function, diagnostics) = compile("while (true) { var a; break; continue; }");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            ops(&function.chunk),
+            [
+                True,
+                JumpIfFalse(14),
+                Pop,
+                Nil,
+                // The locals of the body are discarded before jumping out of it
+                Pop,
+                Jump(9),
+                Pop,
+                Jump(1),
+                Pop,
+                Loop(18),
+                Pop,
+                Nil,
+                Return
+            ]
+        );
+
+        let (_, diagnostics) = compile("break; fun f() { while (true) { fun g() { continue; } } }");
+        assert!(matches!(
+            diagnostics.as_slice(),
+            [
+                CompileError::OutsideLoop { .. },
+                CompileError::OutsideLoop { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn reports_unsupported_lists() {
+        let (_, diagnostics) = compile("var xs = [1, 2]; xs[0] = xs[1];");
+
+        assert!(matches!(
+            diagnostics.as_slice(),
+            [
+                CompileError::Unsupported { .. },
+                CompileError::Unsupported { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn captures_upvalues() {
+        let source = "fun outer() { var x = 1; fun inner() { return x; } return inner; }";
+        let (function, diagnostics) = compile(source);
+
+        assert!(diagnostics.is_empty());
+
+        let outer = match &function.chunk.constants()[0] {
+            Constant::Function(outer) => outer.clone(),
+            _ => panic!("expected function constant"),
+        };
+        assert_eq!(
+            ops(&outer.chunk),
+            [Constant(0), Closure(1), GetLocal(2), Return, Nil, Return]
+        );
+
+        let inner = match &outer.chunk.constants()[1] {
+            Constant::Function(inner) => inner.clone(),
+            _ => panic!("expected function constant"),
+        };
+        assert_eq!(
+            inner.upvalues,
+            vec![UpvalueDescriptor {
+                index: 1,
+                is_local: true
+            }]
+        );
+        assert_eq!(ops(&inner.chunk), [GetUpvalue(0), Return, Nil, Return]);
+    }
+
+    #[test]
+    fn fuses_method_calls() {
+        let source = "class A { f() {} } class B < A { g() { return super.f(1); } } B().g();";
+        let (function, diagnostics) = compile(source);
+
+        assert!(diagnostics.is_empty());
+        let script = ops(&function.chunk);
+        assert_eq!(script[script.len() - 4], Invoke(4, 0));
+
+        let g = function
+            .chunk
+            .constants()
+            .iter()
+            .find_map(|constant| match constant {
+                Constant::Function(g) if g.name == "g" => Some(g.clone()),
+                _ => None,
+            })
+            .expect("expected method g");
+        assert_eq!(
+            ops(&g.chunk),
+            [
+                GetLocal(0),
+                Constant(1),
+                GetUpvalue(0),
+                SuperInvoke(0, 1),
+                Return,
+                Nil,
+                Return
+            ]
+        );
+    }
+
+    #[test]
+    fn records_source_lines() {
+        let (function, diagnostics) = compile("var a = 1;\n\nprint a +\n  2;\n");
+
+        assert!(diagnostics.is_empty());
+        let lines: Vec<_> = function
+            .chunk
+            .instructions()
+            .map(|(offset, _)| function.chunk.line(offset))
+            .collect();
+        assert_eq!(lines, [1, 1, 3, 4, 3, 3, 4, 4]);
+        assert_eq!(
+            function
+                .chunk
+                .to_string()
+                .lines()
+                .take(3)
+                .collect::<Vec<_>>(),
+            [
+                "0000    1 OP_CONSTANT        0 '1'",
+                "0002    | OP_DEFINE_GLOBAL   1 'a'",
+                "0004    3 OP_GET_GLOBAL      1 'a'",
+            ]
+        );
+    }
+
+    #[test]
+    fn deduplicates_constants() {
+        let (function, diagnostics) = compile(r#"print 1 + 1; print "a" + "a"; print -0 + 0;"#);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            function.chunk.constants(),
+            &[
+                Constant::Number(1.0),
+                Constant::String("a".into()),
+                Constant::Number(0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_wide_constants_when_pool_is_full() {
+        let source: String = (0..300).map(|i| format!("print {};", i)).collect();
+        let (function, diagnostics) = compile(&source);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(function.chunk.constants().len(), 300);
+        let ops = ops(&function.chunk);
+        assert_eq!(ops[2 * 255], Constant(255));
+        assert_eq!(ops[2 * 256], ConstantLong(256));
+        assert_eq!(ops[2 * 299], ConstantLong(299));
+    }
+
+    #[test]
+    fn uses_wide_name_operands() {
+        let source: String = (0..300).map(|i| format!("print {};", i)).collect();
+        let (function, diagnostics) = compile(&(source + "var a = 1; fun f() {} a = a;"));
+
+        assert!(diagnostics.is_empty());
+        let ops = ops(&function.chunk);
+        assert_eq!(
+            ops[ops.len() - 9..],
+            [
+                Constant(1),
+                DefineGlobal(300),
+                Closure(301),
+                DefineGlobal(302),
+                GetGlobal(300),
+                SetGlobal(300),
+                Pop,
+                Nil,
+                Return
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_branches_removed_by_the_optimizer() {
+        let source = "while (x) {} if (false) { break; } if (false) return 1;";
+        let mut parser = Parser::new(source);
+        let statements = parser.parse();
+
+        let mut compiler = Compiler::new(source);
+        compiler.compile(&optimize(statements.clone()));
+        assert!(compiler.diagnostics().is_empty());
+
+        compiler.check(&statements);
+        assert!(matches!(
+            compiler.diagnostics(),
+            [
+                CompileError::OutsideLoop { .. },
+                CompileError::ReturnOutsideFn { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn reports_semantic_errors() {
+        let (_, diagnostics) = compile("return 1; { var a = a; } print this;");
+
+        assert!(matches!(
+            diagnostics.as_slice(),
+            [
+                CompileError::ReturnOutsideFn { .. },
+                CompileError::InitializeFromSelf { .. },
+                CompileError::ThisOutsideClass { .. },
+            ]
+        ));
+    }
+}
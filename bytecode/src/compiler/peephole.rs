@@ -0,0 +1,517 @@
+//! A peephole optimizer which is run over each chunk once it has been compiled.
+//!
+//! The chunk is decoded into a list of instructions in which jumps refer to the index of their
+//! target rather than a byte offset, so that instructions can be removed and fused without
+//! invalidating jumps. Once no more rewrites apply the instructions are encoded into a new chunk,
+//! recomputing every jump offset and keeping the line of each instruction.
+
+use crate::chunk::Chunk;
+use crate::opcode::OpCode;
+
+/// Rewrites `chunk` into an equivalent chunk with fewer instructions.
+pub(super) fn optimize(chunk: &Chunk) -> Chunk {
+    let mut peephole = Peephole::decode(chunk);
+    while peephole.rewrite() {}
+    peephole.encode(chunk)
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Instruction {
+    /// Jumps are stored with an offset of 0, unconditional jumps are always stored as `Jump` and
+    /// encoded as a `Loop` if their target is behind them.
+    op: OpCode,
+    line: u32,
+    /// Index of the instruction jumped to by a jump.
+    target: usize,
+    /// Offset of the instruction in the original chunk.
+    offset: usize,
+    removed: bool,
+}
+
+struct Peephole {
+    instructions: Vec<Instruction>,
+    /// Whether each instruction may be the target of a jump, rewrites never remove or fuse an
+    /// instruction which is jumped to unless it is the first of the sequence.
+    targeted: Vec<bool>,
+    /// Length of the original chunk.
+    len: usize,
+}
+
+impl Peephole {
+    fn decode(chunk: &Chunk) -> Self {
+        let mut index_of = vec![0; chunk.len() + 1];
+        let mut instructions = Vec::new();
+        let mut targets = Vec::new();
+
+        for (offset, op) in chunk.instructions() {
+            index_of[offset] = instructions.len();
+
+            let next = offset + op.encoded_len();
+            let (op, target) = match op {
+                OpCode::Jump(o) => (OpCode::Jump(0), next + o as usize),
+                OpCode::Loop(o) => (OpCode::Jump(0), next - o as usize),
+                OpCode::JumpIfFalse(o) => (OpCode::JumpIfFalse(0), next + o as usize),
+                OpCode::JumpIfTrue(o) => (OpCode::JumpIfTrue(0), next + o as usize),
+                op => (op, 0),
+            };
+            targets.push(target);
+
+            instructions.push(Instruction {
+                op,
+                line: chunk.line(offset),
+                target: 0,
+                offset,
+                removed: false,
+            });
+        }
+        index_of[chunk.len()] = instructions.len();
+
+        for (instruction, target) in instructions.iter_mut().zip(targets) {
+            if is_jump(instruction.op) {
+                instruction.target = index_of[target];
+            }
+        }
+
+        Self {
+            targeted: vec![false; instructions.len() + 1],
+            instructions,
+            len: chunk.len(),
+        }
+    }
+
+    /// Applies every rewrite once, returning whether any instructions changed.
+    fn rewrite(&mut self) -> bool {
+        let mut changed = false;
+
+        self.targeted.fill(false);
+        for i in 0..self.instructions.len() {
+            let instruction = self.instructions[i];
+            if !instruction.removed && is_jump(instruction.op) {
+                let target = self.resolve(instruction.target);
+                self.targeted[target] = true;
+            }
+        }
+
+        for i in 0..self.instructions.len() {
+            if self.instructions[i].removed {
+                continue;
+            }
+
+            changed |= self.thread_jump(i)
+                || self.remove_jump_to_next(i)
+                || self.remove_push_pop(i)
+                || self.remove_store_load(i)
+                || self.fuse_local_constant(i)
+                || self.negate_jump(i);
+        }
+
+        changed
+    }
+
+    /// Points a jump whose target is another jump that will always be taken at the final target.
+    fn thread_jump(&mut self, i: usize) -> bool {
+        let op = self.instructions[i].op;
+        if !is_jump(op) {
+            return false;
+        }
+
+        let start = self.resolve(self.instructions[i].target);
+        let mut target = start;
+        // Bounded in case the jumps form a cycle
+        for _ in 0..self.instructions.len() {
+            let next = match self.instructions.get(target) {
+                Some(next) => next,
+                None => break,
+            };
+
+            // A conditional jump doesn't pop its condition so a jump on the same condition at
+            // its target is taken as well.
+            let taken = matches!(
+                (op, next.op),
+                (_, OpCode::Jump(_))
+                    | (OpCode::JumpIfFalse(_), OpCode::JumpIfFalse(_))
+                    | (OpCode::JumpIfTrue(_), OpCode::JumpIfTrue(_))
+            );
+            let next_target = self.resolve(next.target);
+            if !taken || next_target == target || !self.can_jump(i, next_target) {
+                break;
+            }
+            target = next_target;
+        }
+
+        self.instructions[i].target = target;
+        self.targeted[target] = true;
+        target != start
+    }
+
+    fn remove_jump_to_next(&mut self, i: usize) -> bool {
+        if !is_jump(self.instructions[i].op) {
+            return false;
+        }
+
+        let next = self.next(i).unwrap_or(self.instructions.len());
+        if self.resolve(self.instructions[i].target) != next {
+            return false;
+        }
+
+        self.remove(i);
+        true
+    }
+
+    /// Removes a value which is pushed and immediately popped.
+    fn remove_push_pop(&mut self, i: usize) -> bool {
+        use OpCode::*;
+
+        if !matches!(
+            self.instructions[i].op,
+            Nil | True | False | Constant(_) | ConstantLong(_) | GetLocal(_) | GetUpvalue(_)
+        ) {
+            return false;
+        }
+
+        match self.sequence(i) {
+            Some([_, pop]) if matches!(self.instructions[pop].op, Pop) => {
+                self.remove(i);
+                self.remove(pop);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes a `Pop` of an assigned value which is followed by a load of the same variable.
+    fn remove_store_load(&mut self, i: usize) -> bool {
+        use OpCode::*;
+
+        let [pop, load] = match self.sequence(i) {
+            Some([_, pop, load]) => [pop, load],
+            _ => return false,
+        };
+
+        let pair = (self.instructions[i].op, self.instructions[load].op);
+        let matches = matches!(self.instructions[pop].op, Pop)
+            && (matches!(pair, (SetLocal(a), GetLocal(b)) | (SetUpvalue(a), GetUpvalue(b)) if a == b)
+                || matches!(pair, (SetGlobal(a), GetGlobal(b)) if a == b));
+        if matches {
+            self.remove(pop);
+            self.remove(load);
+        }
+        matches
+    }
+
+    /// Fuses a `GetLocal` and `Constant` followed by an arithmetic instruction into a single
+    /// superinstruction.
+    fn fuse_local_constant(&mut self, i: usize) -> bool {
+        let [constant, arithmetic] = match self.sequence(i) {
+            Some([_, constant, arithmetic]) => [constant, arithmetic],
+            _ => return false,
+        };
+
+        let (slot, c) = match (self.instructions[i].op, self.instructions[constant].op) {
+            (OpCode::GetLocal(slot), OpCode::Constant(c)) => (slot, c),
+            _ => return false,
+        };
+        let fused = match self.instructions[arithmetic].op {
+            OpCode::Add => OpCode::AddLocalConstant(slot, c),
+            OpCode::Subtract => OpCode::SubtractLocalConstant(slot, c),
+            OpCode::Less => OpCode::LessLocalConstant(slot, c),
+            _ => return false,
+        };
+
+        // Errors are reported on the line of the arithmetic instruction
+        self.instructions[i].op = fused;
+        self.instructions[i].line = self.instructions[arithmetic].line;
+        self.remove(constant);
+        self.remove(arithmetic);
+        true
+    }
+
+    /// Rewrites `Not` followed by `JumpIfFalse` into `JumpIfTrue`. The condition is left on the
+    /// stack by the jump so this is only done when it is immediately popped on both paths.
+    fn negate_jump(&mut self, i: usize) -> bool {
+        let jump = match self.sequence(i) {
+            Some([_, jump]) => jump,
+            _ => return false,
+        };
+
+        let is_pop = |index: Option<usize>| {
+            index.is_some_and(|index| {
+                self.instructions
+                    .get(index)
+                    .is_some_and(|instruction| matches!(instruction.op, OpCode::Pop))
+            })
+        };
+
+        let negated = matches!(self.instructions[i].op, OpCode::Not)
+            && matches!(self.instructions[jump].op, OpCode::JumpIfFalse(_))
+            && is_pop(self.next(jump))
+            && is_pop(Some(self.resolve(self.instructions[jump].target)));
+        if negated {
+            self.remove(i);
+            self.instructions[jump].op = OpCode::JumpIfTrue(0);
+        }
+        negated
+    }
+
+    /// Returns the indices of the `N` live instructions starting at `i` if none of them other
+    /// than the first are the target of a jump.
+    fn sequence<const N: usize>(&self, i: usize) -> Option<[usize; N]> {
+        let mut indices = [i; N];
+        for k in 1..N {
+            let next = self.next(indices[k - 1])?;
+            if self.targeted[next] {
+                return None;
+            }
+            indices[k] = next;
+        }
+        Some(indices)
+    }
+
+    /// Removes the instruction at `i`, jumps to it now land on the next live instruction.
+    fn remove(&mut self, i: usize) {
+        self.instructions[i].removed = true;
+        if self.targeted[i] {
+            let next = self.resolve(i);
+            self.targeted[next] = true;
+        }
+    }
+
+    /// The index of the next live instruction after `i`.
+    fn next(&self, i: usize) -> Option<usize> {
+        (i + 1..self.instructions.len()).find(|&j| !self.instructions[j].removed)
+    }
+
+    /// The index of the instruction which is executed when jumping to `target`, jumps to a
+    /// removed instruction land on the next live instruction.
+    fn resolve(&self, target: usize) -> usize {
+        (target..self.instructions.len())
+            .find(|&j| !self.instructions[j].removed)
+            .unwrap_or(self.instructions.len())
+    }
+
+    /// Whether the jump at `i` can be pointed at `target`, conditional jumps can only jump forward
+    /// and the offset must fit in a jump's operand. The optimized chunk is never larger than the
+    /// original so checking the offset in the original chunk is enough.
+    fn can_jump(&self, i: usize, target: usize) -> bool {
+        let from = self.instructions[i].offset + OpCode::Jump(0).encoded_len();
+        let to = self
+            .instructions
+            .get(target)
+            .map_or(self.len, |instruction| instruction.offset);
+
+        let forward = target > i;
+        (forward || matches!(self.instructions[i].op, OpCode::Jump(_)))
+            && u16::try_from(from.abs_diff(to)).is_ok()
+    }
+
+    fn encode(&self, original: &Chunk) -> Chunk {
+        // The offset of each instruction in the new chunk, removed instructions share the offset
+        // of the next live instruction.
+        let mut offsets = Vec::with_capacity(self.instructions.len() + 1);
+        let mut offset = 0;
+        for instruction in self.instructions.iter() {
+            offsets.push(offset);
+            if !instruction.removed {
+                offset += instruction.op.encoded_len();
+            }
+        }
+        offsets.push(offset);
+
+        let mut chunk = Chunk::new();
+        for constant in original.constants() {
+            chunk.push_constant(constant.clone());
+        }
+
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            if instruction.removed {
+                continue;
+            }
+
+            let mut op = instruction.op;
+            if is_jump(op) {
+                let from = offsets[i] + op.encoded_len();
+                let to = offsets[instruction.target];
+                let distance = u16::try_from(from.abs_diff(to))
+                    .expect("optimized jump is further than the original");
+
+                op = match op {
+                    OpCode::Jump(_) if to < from => OpCode::Loop(distance),
+                    OpCode::Jump(_) => OpCode::Jump(distance),
+                    OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(distance),
+                    OpCode::JumpIfTrue(_) => OpCode::JumpIfTrue(distance),
+                    op => unreachable!("{:?} is not a jump", op),
+                };
+            }
+
+            chunk.push_op(op, instruction.line);
+        }
+
+        chunk
+    }
+}
+
+fn is_jump(op: OpCode) -> bool {
+    matches!(
+        op,
+        OpCode::Jump(_) | OpCode::JumpIfFalse(_) | OpCode::JumpIfTrue(_) | OpCode::Loop(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use lox_syntax::Parser;
+
+    use super::*;
+    use crate::chunk::Constant;
+    use crate::compiler::Compiler;
+    use crate::function::Function;
+    use crate::verifier::verify;
+    use OpCode::*;
+
+    fn compile(source: &str) -> Function {
+        let mut parser = Parser::new(source);
+        let statements = parser.parse();
+        assert!(parser.diagnostics().is_empty());
+
+        let mut compiler = Compiler::new(source);
+        let function = compiler.compile(&statements);
+        assert!(compiler.diagnostics().is_empty());
+        assert_eq!(verify(&function), Ok(()));
+        function
+    }
+
+    fn ops(chunk: &Chunk) -> Vec<OpCode> {
+        chunk.instructions().map(|(_, op)| op).collect()
+    }
+
+    fn function(script: &Function, name: &str) -> Vec<OpCode> {
+        script
+            .chunk
+            .constants()
+            .iter()
+            .find_map(|constant| match constant {
+                Constant::Function(function) if function.name == name => Some(ops(&function.chunk)),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no function {}", name))
+    }
+
+    #[test]
+    fn removes_redundant_pops() {
+        let script = compile("{ var a; 1; a = 2; print a; }");
+        assert_eq!(
+            ops(&script.chunk),
+            [Nil, Constant(1), SetLocal(1), Print, Pop, Nil, Return]
+        );
+    }
+
+    #[test]
+    fn threads_jumps() {
+        let script = compile("while (a) { if (b) print 1; }");
+        assert_eq!(
+            ops(&script.chunk),
+            [
+                GetGlobal(0),
+                JumpIfFalse(17),
+                Pop,
+                GetGlobal(1),
+                JumpIfFalse(7),
+                Pop,
+                Constant(2),
+                Print,
+                Loop(18),
+                Pop,
+                Loop(22),
+                Pop,
+                Nil,
+                Return
+            ]
+        );
+
+        // The first jump lands on the second which is taken for the same condition
+        let script = compile("if (a and b) print 1;");
+        assert_eq!(ops(&script.chunk)[1], JumpIfFalse(13));
+    }
+
+    #[test]
+    fn fuses_superinstructions() {
+        let script = compile("fun f(n) { if (n < 2) return n; return f(n - 1) + f(n - 2); }");
+        assert_eq!(
+            function(&script, "f"),
+            [
+                LessLocalConstant(1, 0),
+                JumpIfFalse(7),
+                Pop,
+                GetLocal(1),
+                Return,
+                Jump(1),
+                Pop,
+                GetGlobal(1),
+                SubtractLocalConstant(1, 2),
+                Call(1),
+                GetGlobal(1),
+                SubtractLocalConstant(1, 0),
+                Call(1),
+                Add,
+                Return,
+                Nil,
+                Return
+            ]
+        );
+    }
+
+    #[test]
+    fn negates_jumps() {
+        let script = compile("if (!a) print 1; else print 2;");
+        assert_eq!(
+            ops(&script.chunk),
+            [
+                GetGlobal(0),
+                JumpIfTrue(7),
+                Pop,
+                Constant(1),
+                Print,
+                Jump(4),
+                Pop,
+                Constant(2),
+                Print,
+                Nil,
+                Return
+            ]
+        );
+
+        // The negated condition is the value of the expression so must be kept
+        let script = compile("print !a and b;");
+        assert_eq!(ops(&script.chunk)[1..3], [Not, JumpIfFalse(3)]);
+    }
+
+    #[test]
+    fn keeps_lines() {
+        let script = compile("var a = 1;\nif (!a)\n  print a;\nelse {\n  print -a;\n}\n");
+        let lines: Vec<_> = script
+            .chunk
+            .instructions()
+            .map(|(offset, op)| (op, script.chunk.line(offset)))
+            .collect();
+
+        assert_eq!(
+            lines,
+            [
+                (Constant(0), 1),
+                (DefineGlobal(1), 1),
+                (GetGlobal(1), 2),
+                (JumpIfTrue(7), 2),
+                (Pop, 2),
+                (GetGlobal(1), 3),
+                (Print, 3),
+                (Jump(5), 2),
+                (Pop, 2),
+                (GetGlobal(1), 5),
+                (Negate, 5),
+                (Print, 5),
+                (Nil, 6),
+                (Return, 6)
+            ]
+        );
+    }
+}
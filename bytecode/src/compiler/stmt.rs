@@ -0,0 +1,266 @@
+use std::rc::Rc;
+
+use lox_syntax::ast::{
+    expr::{Expr, Literal, Value as LiteralValue},
+    stmt::{
+        Block, Break, ClassDecl, Continue, ExprStmt, FunDecl, If, Print, Return, Stmt, Var, While,
+    },
+};
+use lox_syntax::span::Span;
+
+use crate::chunk::Constant;
+use crate::function::Function;
+use crate::opcode::OpCode;
+
+use super::{end_of, ClassState, CompileError, Compiler, FunctionType, LoopState};
+
+impl Compiler {
+    pub(super) fn compile_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Print(p) => self.compile_print_stmt(p),
+            Stmt::Expr(e) => self.compile_expr_stmt(e),
+            Stmt::Var(v) => self.compile_var_decl(v),
+            Stmt::Block(b) => self.compile_block_stmt(b),
+            Stmt::If(i) => self.compile_if_stmt(i),
+            Stmt::While(w) => self.compile_while_stmt(w),
+            Stmt::Break(b) => self.compile_break_stmt(b),
+            Stmt::Continue(c) => self.compile_continue_stmt(c),
+            Stmt::FunDecl(f) => self.compile_fun_decl(f),
+            Stmt::Return(r) => self.compile_return_stmt(r),
+            Stmt::ClassDecl(c) => self.compile_class_decl(c),
+        }
+    }
+
+    fn compile_print_stmt(&mut self, print: &Print) {
+        self.compile_expr(&print.expr);
+        self.emit(OpCode::Print, print.span);
+    }
+
+    fn compile_expr_stmt(&mut self, expr_stmt: &ExprStmt) {
+        self.compile_expr(&expr_stmt.expr);
+        self.emit(OpCode::Pop, expr_stmt.span);
+    }
+
+    fn compile_var_decl(&mut self, var: &Var) {
+        self.declare_variable(&var.id.name, var.id.span);
+        self.compile_expr(&var.expr);
+        self.define_variable(&var.id.name, var.id.span);
+    }
+
+    fn compile_block_stmt(&mut self, block: &Block) {
+        self.begin_scope();
+        for stmt in block.stmts.iter() {
+            self.compile_stmt(stmt);
+        }
+        self.end_scope(end_of(block.span));
+    }
+
+    fn compile_if_stmt(&mut self, if_stmt: &If) {
+        self.compile_expr(&if_stmt.cond);
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse, if_stmt.span);
+        self.emit(OpCode::Pop, if_stmt.span);
+        self.compile_stmt(&if_stmt.then_stmt);
+
+        let else_jump = self.emit_jump(OpCode::Jump, if_stmt.span);
+        self.patch_jump(then_jump, if_stmt.span);
+        self.emit(OpCode::Pop, if_stmt.span);
+
+        if let Some(else_stmt) = &if_stmt.else_stmt {
+            self.compile_stmt(else_stmt);
+        }
+        self.patch_jump(else_jump, if_stmt.span);
+    }
+
+    fn compile_while_stmt(&mut self, while_stmt: &While) {
+        let loop_start = self.chunk().len();
+        self.compile_expr(&while_stmt.cond);
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse, while_stmt.span);
+        self.emit(OpCode::Pop, while_stmt.span);
+
+        let scope_depth = self.current().scope_depth;
+        self.current_mut().loops.push(LoopState {
+            scope_depth,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        self.compile_stmt(&while_stmt.stmt);
+        let state = self
+            .current_mut()
+            .loops
+            .pop()
+            .expect("no loop is being compiled");
+
+        for jump in state.continues {
+            self.patch_jump(jump, while_stmt.span);
+        }
+        if let Some(increment) = &while_stmt.increment {
+            self.compile_expr(increment);
+            self.emit(OpCode::Pop, increment.span());
+        }
+        self.emit_loop(loop_start, while_stmt.span);
+
+        self.patch_jump(exit_jump, while_stmt.span);
+        self.emit(OpCode::Pop, while_stmt.span);
+        for jump in state.breaks {
+            self.patch_jump(jump, while_stmt.span);
+        }
+    }
+
+    fn compile_break_stmt(&mut self, break_stmt: &Break) {
+        if let Some(jump) = self.emit_loop_jump("break", break_stmt.span) {
+            self.current_loop().breaks.push(jump);
+        }
+    }
+
+    fn compile_continue_stmt(&mut self, continue_stmt: &Continue) {
+        if let Some(jump) = self.emit_loop_jump("continue", continue_stmt.span) {
+            self.current_loop().continues.push(jump);
+        }
+    }
+
+    /// Discards the locals declared within the innermost loop and emits a jump out of its body
+    /// which must be patched once the loop has been compiled, `None` if there is no loop.
+    fn emit_loop_jump(&mut self, keyword: &str, span: Span) -> Option<usize> {
+        let Some(state) = self.current().loops.last() else {
+            self.error(CompileError::OutsideLoop {
+                span,
+                message: format!("can't use `{}` outside of a loop", keyword).into(),
+            });
+            return None;
+        };
+
+        self.discard_locals(state.scope_depth, span);
+        Some(self.emit_jump(OpCode::Jump, span))
+    }
+
+    fn current_loop(&mut self) -> &mut LoopState {
+        self.current_mut()
+            .loops
+            .last_mut()
+            .expect("no loop is being compiled")
+    }
+
+    fn compile_fun_decl(&mut self, fun_decl: &FunDecl) {
+        self.declare_variable(&fun_decl.id.name, fun_decl.id.span);
+        // Functions may refer to themselves recursively so the variable is initialized before
+        // the body is compiled.
+        if self.current().scope_depth > 0 {
+            self.mark_initialized();
+        }
+        self.compile_function(fun_decl, FunctionType::Function);
+        self.define_variable(&fun_decl.id.name, fun_decl.id.span);
+    }
+
+    pub(super) fn compile_function(&mut self, fun_decl: &FunDecl, function_type: FunctionType) {
+        let arity = u8::try_from(fun_decl.params.len()).unwrap_or_else(|_| {
+            self.error(CompileError::TooManyParameters {
+                span: fun_decl.span,
+            });
+            u8::MAX
+        });
+
+        self.begin_function(Function::new(&fun_decl.id.name, arity), function_type);
+        self.begin_scope();
+
+        for param in fun_decl.params.iter() {
+            self.declare_variable(&param.name, param.span);
+            self.define_variable(&param.name, param.span);
+        }
+
+        for stmt in fun_decl.body.iter() {
+            self.compile_stmt(stmt);
+        }
+
+        let function = self.end_function(end_of(fun_decl.span));
+        let constant = self.make_constant(Constant::Function(Rc::new(function)), fun_decl.span);
+        self.emit(OpCode::Closure(constant), fun_decl.span);
+    }
+
+    fn compile_return_stmt(&mut self, return_stmt: &Return) {
+        match self.current().function_type {
+            FunctionType::Script => {
+                self.error(CompileError::ReturnOutsideFn {
+                    span: return_stmt.span,
+                });
+            }
+            FunctionType::Initializer => {
+                if !matches!(
+                    return_stmt.expr,
+                    Expr::Literal(Literal {
+                        value: LiteralValue::Nil,
+                        ..
+                    })
+                ) {
+                    self.error(CompileError::ReturnValueFromInit {
+                        span: return_stmt.span,
+                    });
+                }
+
+                self.emit_return(return_stmt.span);
+                return;
+            }
+            _ => {}
+        }
+
+        self.compile_expr(&return_stmt.expr);
+        self.emit(OpCode::Return, return_stmt.span);
+    }
+
+    fn compile_class_decl(&mut self, class_decl: &ClassDecl) {
+        let id = &class_decl.id;
+        let name = self.identifier_constant(&id.name, id.span);
+        self.declare_variable(&id.name, id.span);
+        self.emit(OpCode::Class(name), class_decl.span);
+        self.define_variable(&id.name, id.span);
+
+        self.classes.push(ClassState {
+            has_super_class: false,
+        });
+
+        if let Some(ref super_class) = class_decl.super_class {
+            if super_class.name == id.name {
+                self.error(CompileError::InheritFromSelf {
+                    span: super_class.span,
+                });
+            }
+
+            self.get_variable(&super_class.name, super_class.span);
+
+            // The super class is stored in a local in a scope surrounding the methods so that
+            // each method captures it as an upvalue.
+            self.begin_scope();
+            self.add_local("super", super_class.span);
+            self.mark_initialized();
+
+            self.get_variable(&id.name, id.span);
+            self.emit(OpCode::Inherit, super_class.span);
+
+            if let Some(class) = self.classes.last_mut() {
+                class.has_super_class = true;
+            }
+        }
+
+        // Load the class so that methods can be bound to it
+        self.get_variable(&id.name, id.span);
+        for method in class_decl.methods.iter() {
+            let name = self.identifier_constant(&method.id.name, method.id.span);
+            let function_type = if method.id.name == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+
+            self.compile_function(method, function_type);
+            self.emit(OpCode::Method(name), method.span);
+        }
+        self.emit(OpCode::Pop, end_of(class_decl.span));
+
+        if class_decl.super_class.is_some() {
+            self.end_scope(end_of(class_decl.span));
+        }
+
+        self.classes.pop();
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt::{Display, Formatter};
+
+use crate::chunk::{Chunk, Constant};
+
+/// A compiled function prototype, closures are created from prototypes at runtime.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: u8,
+    /// Describes where each upvalue of a closure over this function should be captured from.
+    pub upvalues: Vec<UpvalueDescriptor>,
+    pub chunk: Chunk,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UpvalueDescriptor {
+    /// Slot of the local in the enclosing function if `is_local`, else the index of the upvalue
+    /// in the enclosing function.
+    pub index: u8,
+    pub is_local: bool,
+}
+
+impl Function {
+    pub fn new(name: impl Into<String>, arity: u8) -> Self {
+        Self {
+            name: name.into(),
+            arity,
+            ..Default::default()
+        }
+    }
+
+    /// Disassembles the function's chunk followed by the chunks of any nested functions.
+    pub fn disassemble(&self) {
+        self.chunk.disassemble(&self.to_string());
+
+        for constant in self.chunk.constants() {
+            if let Constant::Function(function) = constant {
+                function.disassemble();
+            }
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.name.is_empty() {
+            f.write_str("<script>")
+        } else {
+            write!(f, "<fn {}>", self.name)
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::mem;
+use std::rc::Rc;
+
+use crate::object::{CacheSite, ObjRef, Object, Upvalue};
+use crate::value::{Value, ValueKind};
+
+/// The heap size which must be reached before the first collection.
+const INITIAL_THRESHOLD: usize = 1024 * 1024;
+/// The factor by which the heap may grow after a collection before the next is triggered.
+const GROWTH_FACTOR: usize = 2;
+
+/// Owns every object allocated by the VM, objects are referred to by `ObjRef` handles.
+///
+/// Strings are interned so there is only ever one string object with given contents, strings
+/// can therefore be compared and hashed by their `ObjRef`.
+///
+/// Memory is reclaimed by a mark and sweep garbage collector. The heap doesn't know the VM's
+/// roots, so the owner of the heap checks `should_collect` before allocating, marks its roots
+/// with `mark_value` and `mark_object` and then calls `collect`.
+#[derive(Debug)]
+pub struct Heap {
+    /// `None` for slots whose object has been freed.
+    objects: Vec<Option<Object>>,
+    /// Freed slots which will be reused by later allocations.
+    free: Vec<u32>,
+    marks: Marks,
+    /// The interned strings, entries don't keep their string alive and are removed when it is
+    /// collected.
+    strings: HashMap<Rc<str>, ObjRef>,
+    /// An estimate of the memory owned by live objects.
+    bytes_allocated: usize,
+    next_gc: usize,
+    /// Collect on every allocation to flush out objects which aren't reachable from the roots.
+    stress: bool,
+}
+
+/// The colour of each object during a collection. Unmarked objects are white, marked objects
+/// which are waiting in `gray` to have their references traced are gray and marked objects
+/// which have been traced are black.
+#[derive(Debug, Default)]
+struct Marks {
+    marked: Vec<bool>,
+    gray: Vec<ObjRef>,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            objects: Vec::new(),
+            free: Vec::new(),
+            marks: Marks::default(),
+            strings: HashMap::new(),
+            bytes_allocated: 0,
+            next_gc: INITIAL_THRESHOLD,
+            stress: false,
+        }
+    }
+
+    /// Allocates `object`, this never collects garbage so it is up to the caller to make sure
+    /// that collections happen. Strings must be allocated with `intern`.
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        debug_assert!(
+            !matches!(object, Object::String(_)),
+            "strings must be interned"
+        );
+        self.alloc_object(object)
+    }
+
+    /// Returns the string with the contents `string`, allocating it if it hasn't already been
+    /// interned.
+    pub fn intern(&mut self, string: &str) -> ObjRef {
+        if let Some(&obj) = self.strings.get(string) {
+            return obj;
+        }
+
+        let string: Rc<str> = string.into();
+        let obj = self.alloc_object(Object::String(string.clone()));
+        self.strings.insert(string, obj);
+        obj
+    }
+
+    fn alloc_object(&mut self, object: Object) -> ObjRef {
+        self.bytes_allocated += size_of(&object);
+
+        match self.free.pop() {
+            Some(slot) => {
+                self.objects[slot as usize] = Some(object);
+                ObjRef(slot)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marks.marked.push(false);
+                ObjRef(self.objects.len() as u32 - 1)
+            }
+        }
+    }
+
+    pub fn get(&self, obj: ObjRef) -> &Object {
+        self.objects[obj.0 as usize]
+            .as_ref()
+            .expect("use of freed object")
+    }
+
+    pub fn get_mut(&mut self, obj: ObjRef) -> &mut Object {
+        self.objects[obj.0 as usize]
+            .as_mut()
+            .expect("use of freed object")
+    }
+
+    /// Whether a collection should be run before the next allocation.
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.bytes_allocated > self.next_gc
+    }
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    /// An estimate of the memory owned by live objects, objects which have become unreachable
+    /// are counted until they are collected.
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    /// The number of objects which haven't been freed.
+    pub fn live_objects(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+
+    /// Marks `value` as reachable if it is an object.
+    pub fn mark_value(&mut self, value: Value) {
+        self.marks.mark_value(value);
+    }
+
+    /// Marks `obj` as reachable.
+    pub fn mark_object(&mut self, obj: ObjRef) {
+        self.marks.mark_object(obj);
+    }
+
+    /// Frees every object which isn't reachable from the objects marked since the last
+    /// collection.
+    pub fn collect(&mut self) {
+        self.trace_references();
+        self.sweep();
+        self.next_gc = usize::max(self.bytes_allocated * GROWTH_FACTOR, INITIAL_THRESHOLD);
+    }
+
+    /// Blackens gray objects until every object reachable from the roots has been marked.
+    fn trace_references(&mut self) {
+        while let Some(obj) = self.marks.gray.pop() {
+            let marks = &mut self.marks;
+            match self.objects[obj.0 as usize].as_ref() {
+                Some(Object::Function(function)) => {
+                    function.constants.iter().for_each(|&c| marks.mark_value(c));
+                    for cache in function.caches.iter().filter_map(|site| site.cache.get()) {
+                        marks.mark_object(cache.class);
+                        marks.mark_object(cache.method);
+                    }
+                }
+                Some(Object::Closure(closure)) => {
+                    marks.mark_object(closure.function);
+                    closure.upvalues.iter().for_each(|&u| marks.mark_object(u));
+                }
+                Some(Object::Upvalue(Upvalue::Closed(value))) => marks.mark_value(*value),
+                Some(Object::Class(class)) => {
+                    marks.mark_object(class.name);
+                    for (&name, &method) in class.methods.iter() {
+                        marks.mark_object(name);
+                        marks.mark_object(method);
+                    }
+                }
+                Some(Object::Instance(instance)) => {
+                    marks.mark_object(instance.class);
+                    for (&name, &value) in instance.fields.iter() {
+                        marks.mark_object(name);
+                        marks.mark_value(value);
+                    }
+                }
+                Some(Object::BoundMethod(bound)) => {
+                    marks.mark_value(bound.receiver);
+                    marks.mark_object(bound.method);
+                }
+                Some(Object::String(_) | Object::Native(_) | Object::Upvalue(Upvalue::Open(_))) => {
+                }
+                None => panic!("freed object {:?} is reachable", obj),
+            }
+        }
+    }
+
+    /// Frees every white object and resets the marks for the next collection.
+    fn sweep(&mut self) {
+        let marked = &self.marks.marked;
+        self.strings.retain(|_, obj| marked[obj.0 as usize]);
+
+        self.bytes_allocated = 0;
+
+        for (slot, object) in self.objects.iter_mut().enumerate() {
+            let marked = mem::replace(&mut self.marks.marked[slot], false);
+            match object {
+                Some(live) if marked => self.bytes_allocated += size_of(live),
+                Some(_) => {
+                    *object = None;
+                    self.free.push(slot as u32);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Returns the contents of the string `obj`, panics if `obj` is not a string.
+    pub fn string(&self, obj: ObjRef) -> &str {
+        match self.get(obj) {
+            Object::String(s) => s,
+            object => panic!("expected string found {:?}", object),
+        }
+    }
+
+    /// Lox equality, objects are compared by identity which for interned strings is the same
+    /// as comparing their contents.
+    pub fn values_equal(&self, a: Value, b: Value) -> bool {
+        match (a.kind(), b.kind()) {
+            (ValueKind::Nil, ValueKind::Nil) => true,
+            (ValueKind::Boolean(l), ValueKind::Boolean(r)) => l == r,
+            (ValueKind::Number(l), ValueKind::Number(r)) => l == r,
+            (ValueKind::Object(l), ValueKind::Object(r)) => l == r,
+            _ => false,
+        }
+    }
+
+    /// Formats `value` the same way as the tree-walk interpreter.
+    pub fn display(&self, value: Value) -> ValueDisplay<'_> {
+        ValueDisplay { heap: self, value }
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Marks {
+    fn mark_value(&mut self, value: Value) {
+        if let Some(obj) = value.as_object() {
+            self.mark_object(obj);
+        }
+    }
+
+    fn mark_object(&mut self, obj: ObjRef) {
+        let marked = &mut self.marked[obj.0 as usize];
+        if !*marked {
+            *marked = true;
+            self.gray.push(obj);
+        }
+    }
+}
+
+/// An estimate of the memory owned by `object`.
+fn size_of(object: &Object) -> usize {
+    let owned = match object {
+        Object::String(s) => s.len(),
+        Object::Function(function) => {
+            function.constants.len() * mem::size_of::<Value>()
+                + function.caches.len() * mem::size_of::<CacheSite>()
+        }
+        Object::Closure(closure) => closure.upvalues.len() * mem::size_of::<ObjRef>(),
+        Object::Class(class) => class.methods.len() * 2 * mem::size_of::<ObjRef>(),
+        Object::Instance(instance) => {
+            instance.fields.len() * (mem::size_of::<ObjRef>() + mem::size_of::<Value>())
+        }
+        Object::Native(_) | Object::Upvalue(_) | Object::BoundMethod(_) => 0,
+    };
+
+    mem::size_of::<Object>() + owned
+}
+
+pub struct ValueDisplay<'a> {
+    heap: &'a Heap,
+    value: Value,
+}
+
+impl ValueDisplay<'_> {
+    fn fmt_object(&self, f: &mut Formatter<'_>, obj: ObjRef) -> std::fmt::Result {
+        let heap = self.heap;
+
+        match heap.get(obj) {
+            Object::String(s) => write!(f, "\"{}\"", s),
+            Object::Function(function) => write!(f, "{}", function.function),
+            Object::Native(native) => write!(f, "<fn {}>", native.name),
+            Object::Closure(closure) => self.fmt_object(f, closure.function),
+            Object::Upvalue(_) => f.write_str("upvalue"),
+            Object::Class(class) => f.write_str(heap.string(class.name)),
+            Object::Instance(instance) => {
+                self.fmt_object(f, instance.class)?;
+                f.write_str(" instance")
+            }
+            Object::BoundMethod(bound) => self.fmt_object(f, bound.method),
+        }
+    }
+}
+
+impl Display for ValueDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.value.kind() {
+            ValueKind::Nil => f.write_str("nil"),
+            ValueKind::Boolean(b) => write!(f, "{}", b),
+            ValueKind::Number(n) => write!(f, "{}", n),
+            ValueKind::Object(obj) => self.fmt_object(f, obj),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_strings() {
+        let mut heap = Heap::new();
+        let a = heap.intern("lox");
+        let b = heap.intern(&String::from("lox"));
+        let c = heap.intern("clox");
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(heap.values_equal(Value::object(a), Value::object(b)));
+        assert_eq!(heap.live_objects(), 2);
+    }
+
+    #[test]
+    fn sweeps_unreachable_interned_strings() {
+        let mut heap = Heap::new();
+        let kept = heap.intern("kept");
+        heap.intern("swept");
+
+        heap.mark_object(kept);
+        heap.collect();
+
+        assert_eq!(heap.live_objects(), 1);
+        assert_eq!(heap.intern("kept"), kept);
+        let swept = heap.intern("swept");
+        assert_eq!(heap.string(swept), "swept");
+        assert_eq!(heap.live_objects(), 2);
+    }
+}
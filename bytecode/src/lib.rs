@@ -1,5 +1,24 @@
 mod chunk;
+mod compiler;
+mod function;
+mod heap;
+pub mod loader;
+mod object;
+mod opcode;
 mod value;
-pub use chunk::Chunk;
-pub use chunk::OpCode;
-pub use value::Value;
+mod verifier;
+mod vm;
+pub use chunk::{Chunk, Constant, InstructionDisplay, Instructions, LoadError};
+pub use compiler::{CompileError, Compiler};
+pub use function::{Function, UpvalueDescriptor};
+pub use heap::Heap;
+pub use object::{
+    BoundMethod, CacheSite, Class, Closure, InlineCache, Instance, Native, NativeFn, ObjFunction,
+    ObjRef, Object, Upvalue,
+};
+pub use opcode::OpCode;
+pub use value::{Value, ValueKind};
+pub use verifier::{verify, VerifyError, VerifyErrorKind};
+pub use vm::{
+    CacheStats, RResult, RuntimeError, TraceFrame, TracedError, Vm, DEFAULT_MAX_CALL_DEPTH,
+};
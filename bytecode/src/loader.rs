@@ -0,0 +1,71 @@
+//! Loads scripts which have been compiled ahead of time to the `.loxc` format.
+
+use std::io::Read;
+
+use crate::chunk::{Chunk, LoadError};
+use crate::function::Function;
+use crate::verifier::verify;
+
+/// Reads a compiled script from `reader` and verifies it so that it is safe for the VM to run.
+pub fn load(reader: &mut impl Read) -> Result<Function, LoadError> {
+    let script = Function {
+        chunk: Chunk::read_from(reader)?,
+        ..Function::new("", 0)
+    };
+
+    verify(&script).map_err(LoadError::Invalid)?;
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use lox_syntax::Parser;
+
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::opcode::OpCode;
+    use crate::verifier::{VerifyError, VerifyErrorKind};
+
+    fn compile(source: &str) -> Function {
+        let mut parser = Parser::new(source);
+        let statements = parser.parse();
+        let mut compiler = Compiler::new(source);
+        compiler.compile(&statements)
+    }
+
+    fn round_trip(chunk: &Chunk) -> Result<Function, LoadError> {
+        let mut bytes = Vec::new();
+        chunk.write_to(&mut bytes).unwrap();
+        load(&mut bytes.as_slice())
+    }
+
+    #[test]
+    fn loads_compiled_scripts() {
+        let script = compile("fun f(a) { return a + 1; } print f(1);");
+        assert_eq!(round_trip(&script.chunk).unwrap(), script);
+    }
+
+    #[test]
+    fn rejects_invalid_instructions() {
+        let mut chunk = Chunk::new();
+        chunk.push_op(OpCode::GetGlobal(3), 1);
+        chunk.push_op(OpCode::Return, 1);
+        assert!(matches!(
+            round_trip(&chunk),
+            Err(LoadError::Invalid(VerifyError {
+                kind: VerifyErrorKind::ConstantOutOfRange { index: 3 },
+                ..
+            }))
+        ));
+
+        let mut chunk = Chunk::new();
+        chunk.push_op(OpCode::Nil, 1);
+        assert!(matches!(
+            round_trip(&chunk),
+            Err(LoadError::Invalid(VerifyError {
+                kind: VerifyErrorKind::FallsOffEnd,
+                ..
+            }))
+        ));
+    }
+}
@@ -0,0 +1,127 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::function::Function;
+use crate::value::Value;
+use crate::vm::RResult;
+
+/// A handle to an object allocated on the VM's `Heap`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ObjRef(pub(crate) u32);
+
+#[derive(Debug)]
+pub enum Object {
+    String(Rc<str>),
+    Function(ObjFunction),
+    Native(Native),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+}
+
+impl Object {
+    /// Returns the class, panics if the object is not a class.
+    pub fn as_class(&self) -> &Class {
+        match self {
+            Object::Class(class) => class,
+            object => panic!("expected class found {:?}", object),
+        }
+    }
+
+    /// Returns the class, panics if the object is not a class.
+    pub fn as_class_mut(&mut self) -> &mut Class {
+        match self {
+            Object::Class(class) => class,
+            object => panic!("expected class found {:?}", object),
+        }
+    }
+
+    /// Returns the instance, panics if the object is not an instance.
+    pub fn as_instance(&self) -> &Instance {
+        match self {
+            Object::Instance(instance) => instance,
+            object => panic!("expected instance found {:?}", object),
+        }
+    }
+
+    /// Returns the instance, panics if the object is not an instance.
+    pub fn as_instance_mut(&mut self) -> &mut Instance {
+        match self {
+            Object::Instance(instance) => instance,
+            object => panic!("expected instance found {:?}", object),
+        }
+    }
+}
+
+/// A function prototype that has been loaded into the VM.
+#[derive(Debug)]
+pub struct ObjFunction {
+    pub function: Rc<Function>,
+    /// The chunk's constant pool converted into runtime values.
+    pub constants: Rc<[Value]>,
+    /// The inline caches of the chunk's `GetProperty` and `Invoke` instructions, ordered by the
+    /// offset of the instruction so that a call site can find its cache with a binary search.
+    pub caches: Rc<[CacheSite]>,
+}
+
+/// The inline cache of the `GetProperty` or `Invoke` instruction at `offset` in a chunk.
+#[derive(Debug)]
+pub struct CacheSite {
+    pub offset: usize,
+    pub cache: Cell<Option<InlineCache>>,
+}
+
+/// The method found for the last class seen at a call site. Methods can't be added to a class
+/// once it has been declared so the cache stays valid for as long as the class is the same.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InlineCache {
+    pub class: ObjRef,
+    pub method: ObjRef,
+}
+
+pub type NativeFn = fn(&[Value]) -> RResult<Value>;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Native {
+    pub name: &'static str,
+    pub arity: u8,
+    pub function: NativeFn,
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+}
+
+/// A variable captured by a closure.
+#[derive(Debug)]
+pub enum Upvalue {
+    /// The variable is still live on the stack at the given slot.
+    Open(usize),
+    /// The variable has gone out of scope and has been moved into the upvalue.
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: ObjRef,
+    /// Methods keyed by their interned name.
+    pub methods: HashMap<ObjRef, ObjRef>,
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: ObjRef,
+    /// Fields keyed by their interned name.
+    pub fields: HashMap<ObjRef, Value>,
+}
+
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
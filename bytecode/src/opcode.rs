@@ -0,0 +1,402 @@
+/// A single decoded instruction along with its operands.
+///
+/// Instructions are stored in a `Chunk` as a tag byte followed by their operands, single byte
+/// operands are stored as is, jump offsets take two bytes and the index of `ConstantLong` takes
+/// three, multi byte operands are big endian.
+///
+/// `ConstantLong` loads constants whose index doesn't fit in a byte, its operand is limited to
+/// 24 bits. `Invoke` and `SuperInvoke` take the constant holding the method's name followed by
+/// the number of arguments.
+///
+/// The other instructions taking a constant, such as `GetGlobal` and `Closure`, use a byte for
+/// indices which fit in one. Larger indices, up to 24 bits, are encoded by prefixing the
+/// instruction with a `WIDE` tag and storing the index in three bytes.
+///
+/// Jump offsets are in bytes and are relative to the end of the jump instruction, `Jump`,
+/// `JumpIfFalse` and `JumpIfTrue` jump forwards whereas `Loop` jumps backwards.
+///
+/// `AddLocalConstant`, `SubtractLocalConstant` and `LessLocalConstant` are superinstructions
+/// emitted by the peephole optimizer, they take a slot followed by a constant and behave the same
+/// as a `GetLocal` and `Constant` followed by the arithmetic instruction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OpCode {
+    Return,
+    Constant(u8),
+    ConstantLong(u32),
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal(u8),
+    SetLocal(u8),
+    GetGlobal(u32),
+    DefineGlobal(u32),
+    SetGlobal(u32),
+    GetUpvalue(u8),
+    SetUpvalue(u8),
+    GetProperty(u32),
+    SetProperty(u32),
+    GetSuper(u32),
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump(u16),
+    JumpIfFalse(u16),
+    JumpIfTrue(u16),
+    Loop(u16),
+    Call(u8),
+    Closure(u32),
+    CloseUpvalue,
+    Class(u32),
+    Inherit,
+    Method(u32),
+    Invoke(u32, u8),
+    SuperInvoke(u32, u8),
+    AddLocalConstant(u8, u8),
+    SubtractLocalConstant(u8, u8),
+    LessLocalConstant(u8, u8),
+}
+
+/// The tag bytes identifying each instruction.
+mod tag {
+    pub const RETURN: u8 = 0;
+    pub const CONSTANT: u8 = 1;
+    pub const CONSTANT_LONG: u8 = 2;
+    pub const NIL: u8 = 3;
+    pub const TRUE: u8 = 4;
+    pub const FALSE: u8 = 5;
+    pub const POP: u8 = 6;
+    pub const GET_LOCAL: u8 = 7;
+    pub const SET_LOCAL: u8 = 8;
+    pub const GET_GLOBAL: u8 = 9;
+    pub const DEFINE_GLOBAL: u8 = 10;
+    pub const SET_GLOBAL: u8 = 11;
+    pub const GET_UPVALUE: u8 = 12;
+    pub const SET_UPVALUE: u8 = 13;
+    pub const GET_PROPERTY: u8 = 14;
+    pub const SET_PROPERTY: u8 = 15;
+    pub const GET_SUPER: u8 = 16;
+    pub const EQUAL: u8 = 17;
+    pub const GREATER: u8 = 18;
+    pub const LESS: u8 = 19;
+    pub const ADD: u8 = 20;
+    pub const SUBTRACT: u8 = 21;
+    pub const MULTIPLY: u8 = 22;
+    pub const DIVIDE: u8 = 23;
+    pub const NOT: u8 = 24;
+    pub const NEGATE: u8 = 25;
+    pub const PRINT: u8 = 26;
+    pub const JUMP: u8 = 27;
+    pub const JUMP_IF_FALSE: u8 = 28;
+    pub const LOOP: u8 = 29;
+    pub const CALL: u8 = 30;
+    pub const CLOSURE: u8 = 31;
+    pub const CLOSE_UPVALUE: u8 = 32;
+    pub const CLASS: u8 = 33;
+    pub const INHERIT: u8 = 34;
+    pub const METHOD: u8 = 35;
+    pub const INVOKE: u8 = 36;
+    pub const SUPER_INVOKE: u8 = 37;
+    pub const JUMP_IF_TRUE: u8 = 38;
+    pub const ADD_LOCAL_CONSTANT: u8 = 39;
+    pub const SUBTRACT_LOCAL_CONSTANT: u8 = 40;
+    pub const LESS_LOCAL_CONSTANT: u8 = 41;
+    /// Prefixes an instruction whose constant index is stored in three bytes.
+    pub const WIDE: u8 = 42;
+}
+
+impl OpCode {
+    /// Appends the encoding of the instruction to `code`.
+    pub fn encode(&self, code: &mut Vec<u8>) {
+        use OpCode::*;
+
+        let (tag, operand) = match *self {
+            Return => (tag::RETURN, Operand::None),
+            Constant(c) => (tag::CONSTANT, Operand::Byte(c)),
+            ConstantLong(c) => (tag::CONSTANT_LONG, Operand::Long(c)),
+            Nil => (tag::NIL, Operand::None),
+            True => (tag::TRUE, Operand::None),
+            False => (tag::FALSE, Operand::None),
+            Pop => (tag::POP, Operand::None),
+            GetLocal(slot) => (tag::GET_LOCAL, Operand::Byte(slot)),
+            SetLocal(slot) => (tag::SET_LOCAL, Operand::Byte(slot)),
+            GetGlobal(c) => (tag::GET_GLOBAL, Operand::Index(c)),
+            DefineGlobal(c) => (tag::DEFINE_GLOBAL, Operand::Index(c)),
+            SetGlobal(c) => (tag::SET_GLOBAL, Operand::Index(c)),
+            GetUpvalue(slot) => (tag::GET_UPVALUE, Operand::Byte(slot)),
+            SetUpvalue(slot) => (tag::SET_UPVALUE, Operand::Byte(slot)),
+            GetProperty(c) => (tag::GET_PROPERTY, Operand::Index(c)),
+            SetProperty(c) => (tag::SET_PROPERTY, Operand::Index(c)),
+            GetSuper(c) => (tag::GET_SUPER, Operand::Index(c)),
+            Equal => (tag::EQUAL, Operand::None),
+            Greater => (tag::GREATER, Operand::None),
+            Less => (tag::LESS, Operand::None),
+            Add => (tag::ADD, Operand::None),
+            Subtract => (tag::SUBTRACT, Operand::None),
+            Multiply => (tag::MULTIPLY, Operand::None),
+            Divide => (tag::DIVIDE, Operand::None),
+            Not => (tag::NOT, Operand::None),
+            Negate => (tag::NEGATE, Operand::None),
+            Print => (tag::PRINT, Operand::None),
+            Jump(offset) => (tag::JUMP, Operand::Short(offset)),
+            JumpIfFalse(offset) => (tag::JUMP_IF_FALSE, Operand::Short(offset)),
+            JumpIfTrue(offset) => (tag::JUMP_IF_TRUE, Operand::Short(offset)),
+            Loop(offset) => (tag::LOOP, Operand::Short(offset)),
+            Call(args) => (tag::CALL, Operand::Byte(args)),
+            Closure(c) => (tag::CLOSURE, Operand::Index(c)),
+            CloseUpvalue => (tag::CLOSE_UPVALUE, Operand::None),
+            Class(c) => (tag::CLASS, Operand::Index(c)),
+            Inherit => (tag::INHERIT, Operand::None),
+            Method(c) => (tag::METHOD, Operand::Index(c)),
+            Invoke(c, args) => (tag::INVOKE, Operand::IndexPair(c, args)),
+            SuperInvoke(c, args) => (tag::SUPER_INVOKE, Operand::IndexPair(c, args)),
+            AddLocalConstant(slot, c) => (tag::ADD_LOCAL_CONSTANT, Operand::Pair(slot, c)),
+            SubtractLocalConstant(slot, c) => {
+                (tag::SUBTRACT_LOCAL_CONSTANT, Operand::Pair(slot, c))
+            }
+            LessLocalConstant(slot, c) => (tag::LESS_LOCAL_CONSTANT, Operand::Pair(slot, c)),
+        };
+
+        let index = match operand {
+            Operand::Index(c) | Operand::IndexPair(c, _) => Some(c),
+            _ => None,
+        };
+        let wide = index.is_some_and(|c| c > u8::MAX.into());
+        if wide {
+            code.push(tag::WIDE);
+        }
+
+        code.push(tag);
+        match operand {
+            Operand::None => {}
+            Operand::Byte(b) => code.push(b),
+            Operand::Pair(a, b) => code.extend([a, b]),
+            Operand::Short(s) => code.extend(s.to_be_bytes()),
+            Operand::Long(l) => code.extend(&l.to_be_bytes()[1..]),
+            Operand::Index(c) | Operand::IndexPair(c, _) if wide => {
+                code.extend(&c.to_be_bytes()[1..])
+            }
+            Operand::Index(c) | Operand::IndexPair(c, _) => code.push(c as u8),
+        }
+        if let Operand::IndexPair(_, b) = operand {
+            code.push(b);
+        }
+    }
+
+    /// Decodes the instruction at the start of `code`, returning it along with the length of its
+    /// encoding or `None` if `code` doesn't begin with a valid instruction.
+    pub fn decode(code: &[u8]) -> Option<(OpCode, usize)> {
+        use OpCode::*;
+
+        if code.first() == Some(&tag::WIDE) {
+            return Self::decode_wide(&code[1..]);
+        }
+
+        let (&tag, operands) = code.split_first()?;
+        let byte = || operands.first().copied();
+        let short = || Some(u16::from_be_bytes([*operands.first()?, *operands.get(1)?]));
+        let long = || match operands.get(..3)? {
+            &[a, b, c] => Some(u32::from_be_bytes([0, a, b, c])),
+            _ => None,
+        };
+
+        let op = match tag {
+            tag::RETURN => Return,
+            tag::CONSTANT => Constant(byte()?),
+            tag::CONSTANT_LONG => ConstantLong(long()?),
+            tag::NIL => Nil,
+            tag::TRUE => True,
+            tag::FALSE => False,
+            tag::POP => Pop,
+            tag::GET_LOCAL => GetLocal(byte()?),
+            tag::SET_LOCAL => SetLocal(byte()?),
+            tag::GET_GLOBAL => GetGlobal(byte()?.into()),
+            tag::DEFINE_GLOBAL => DefineGlobal(byte()?.into()),
+            tag::SET_GLOBAL => SetGlobal(byte()?.into()),
+            tag::GET_UPVALUE => GetUpvalue(byte()?),
+            tag::SET_UPVALUE => SetUpvalue(byte()?),
+            tag::GET_PROPERTY => GetProperty(byte()?.into()),
+            tag::SET_PROPERTY => SetProperty(byte()?.into()),
+            tag::GET_SUPER => GetSuper(byte()?.into()),
+            tag::EQUAL => Equal,
+            tag::GREATER => Greater,
+            tag::LESS => Less,
+            tag::ADD => Add,
+            tag::SUBTRACT => Subtract,
+            tag::MULTIPLY => Multiply,
+            tag::DIVIDE => Divide,
+            tag::NOT => Not,
+            tag::NEGATE => Negate,
+            tag::PRINT => Print,
+            tag::JUMP => Jump(short()?),
+            tag::JUMP_IF_FALSE => JumpIfFalse(short()?),
+            tag::JUMP_IF_TRUE => JumpIfTrue(short()?),
+            tag::LOOP => Loop(short()?),
+            tag::CALL => Call(byte()?),
+            tag::CLOSURE => Closure(byte()?.into()),
+            tag::CLOSE_UPVALUE => CloseUpvalue,
+            tag::CLASS => Class(byte()?.into()),
+            tag::INHERIT => Inherit,
+            tag::METHOD => Method(byte()?.into()),
+            tag::INVOKE => Invoke(byte()?.into(), *operands.get(1)?),
+            tag::SUPER_INVOKE => SuperInvoke(byte()?.into(), *operands.get(1)?),
+            tag::ADD_LOCAL_CONSTANT => AddLocalConstant(byte()?, *operands.get(1)?),
+            tag::SUBTRACT_LOCAL_CONSTANT => SubtractLocalConstant(byte()?, *operands.get(1)?),
+            tag::LESS_LOCAL_CONSTANT => LessLocalConstant(byte()?, *operands.get(1)?),
+            _ => return None,
+        };
+
+        Some((op, op.encoded_len()))
+    }
+
+    /// Decodes an instruction following a `WIDE` prefix. Only instructions taking a constant
+    /// index can be prefixed and the index must not fit in a byte, so that each instruction has
+    /// a single encoding.
+    fn decode_wide(code: &[u8]) -> Option<(OpCode, usize)> {
+        use OpCode::*;
+
+        let (&tag, operands) = code.split_first()?;
+        let index = match operands.get(..3)? {
+            &[a, b, c] => u32::from_be_bytes([0, a, b, c]),
+            _ => return None,
+        };
+        if index <= u8::MAX.into() {
+            return None;
+        }
+
+        let op = match tag {
+            tag::GET_GLOBAL => GetGlobal(index),
+            tag::DEFINE_GLOBAL => DefineGlobal(index),
+            tag::SET_GLOBAL => SetGlobal(index),
+            tag::GET_PROPERTY => GetProperty(index),
+            tag::SET_PROPERTY => SetProperty(index),
+            tag::GET_SUPER => GetSuper(index),
+            tag::CLOSURE => Closure(index),
+            tag::CLASS => Class(index),
+            tag::METHOD => Method(index),
+            tag::INVOKE => Invoke(index, *operands.get(3)?),
+            tag::SUPER_INVOKE => SuperInvoke(index, *operands.get(3)?),
+            _ => return None,
+        };
+
+        Some((op, op.encoded_len()))
+    }
+
+    /// The number of bytes taken up by the encoded instruction.
+    pub fn encoded_len(&self) -> usize {
+        use OpCode::*;
+
+        // The prefix and the two extra bytes of the index
+        let wide = match *self {
+            GetGlobal(c)
+            | DefineGlobal(c)
+            | SetGlobal(c)
+            | GetProperty(c)
+            | SetProperty(c)
+            | GetSuper(c)
+            | Closure(c)
+            | Class(c)
+            | Method(c)
+            | Invoke(c, _)
+            | SuperInvoke(c, _)
+                if c > u8::MAX.into() =>
+            {
+                3
+            }
+            _ => 0,
+        };
+
+        wide + match self {
+            ConstantLong(_) => 4,
+            Jump(_)
+            | JumpIfFalse(_)
+            | JumpIfTrue(_)
+            | Loop(_)
+            | Invoke(..)
+            | SuperInvoke(..)
+            | AddLocalConstant(..)
+            | SubtractLocalConstant(..)
+            | LessLocalConstant(..) => 3,
+            Constant(_) | GetLocal(_) | SetLocal(_) | GetGlobal(_) | DefineGlobal(_)
+            | SetGlobal(_) | GetUpvalue(_) | SetUpvalue(_) | GetProperty(_) | SetProperty(_)
+            | GetSuper(_) | Call(_) | Closure(_) | Class(_) | Method(_) => 2,
+            Return | Nil | True | False | Pop | Equal | Greater | Less | Add | Subtract
+            | Multiply | Divide | Not | Negate | Print | CloseUpvalue | Inherit => 1,
+        }
+    }
+}
+
+enum Operand {
+    None,
+    Byte(u8),
+    Pair(u8, u8),
+    Short(u16),
+    Long(u32),
+    /// A constant index, which is widened to three bytes if it doesn't fit in one.
+    Index(u32),
+    /// A constant index followed by a byte.
+    IndexPair(u32, u8),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_instructions() {
+        let ops = [
+            OpCode::Return,
+            OpCode::Constant(7),
+            OpCode::ConstantLong(0x01_02_03),
+            OpCode::GetLocal(255),
+            OpCode::JumpIfFalse(0x1234),
+            OpCode::Loop(3),
+            OpCode::Method(1),
+            OpCode::Invoke(2, 3),
+            OpCode::GetGlobal(0x01_02_03),
+            OpCode::SuperInvoke(256, 4),
+        ];
+
+        let mut code = Vec::new();
+        for op in ops.iter() {
+            op.encode(&mut code);
+        }
+        assert_eq!(code.len(), ops.iter().map(OpCode::encoded_len).sum());
+        assert_eq!(
+            &code[1..7],
+            &[tag::CONSTANT, 7, tag::CONSTANT_LONG, 1, 2, 3]
+        );
+
+        let mut decoded = Vec::new();
+        let mut offset = 0;
+        while let Some((op, len)) = OpCode::decode(&code[offset..]) {
+            decoded.push(op);
+            offset += len;
+        }
+        assert_eq!(decoded, ops);
+        assert_eq!(offset, code.len());
+    }
+
+    #[test]
+    fn rejects_malformed_instructions() {
+        assert_eq!(OpCode::decode(&[]), None);
+        assert_eq!(OpCode::decode(&[0xff]), None);
+        assert_eq!(OpCode::decode(&[tag::JUMP, 0]), None);
+        assert_eq!(OpCode::decode(&[tag::CONSTANT_LONG, 0, 0]), None);
+        assert_eq!(OpCode::decode(&[tag::INVOKE, 0]), None);
+        // Only instructions taking a constant can be widened and only when the index needs it
+        assert_eq!(OpCode::decode(&[tag::WIDE, tag::CONSTANT, 1, 0, 0]), None);
+        assert_eq!(
+            OpCode::decode(&[tag::WIDE, tag::GET_GLOBAL, 0, 0, 255]),
+            None
+        );
+        assert_eq!(OpCode::decode(&[tag::WIDE, tag::INVOKE, 0, 1, 0]), None);
+    }
+}
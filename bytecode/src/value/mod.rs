@@ -0,0 +1,114 @@
+use std::fmt::{Debug, Formatter};
+
+use crate::object::ObjRef;
+
+#[cfg(feature = "nan-boxing")]
+pub use nan_boxed::Value;
+#[cfg(not(feature = "nan-boxing"))]
+pub use tagged::Value;
+
+#[cfg(feature = "nan-boxing")]
+mod nan_boxed;
+#[cfg(not(feature = "nan-boxing"))]
+mod tagged;
+
+/// The unpacked form of a `Value`, values are converted to a kind to be matched on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ValueKind {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Object(ObjRef),
+}
+
+/// `Value` wraps a `ValueKind` by default, with the `nan-boxing` feature enabled it is packed
+/// into a single 64 bit word. Both representations provide `NIL`, `boolean`, `number`, `object`
+/// and `kind`, the rest of the API is shared.
+impl Value {
+    pub fn is_truthy(self) -> bool {
+        !matches!(self.kind(), ValueKind::Nil | ValueKind::Boolean(false))
+    }
+
+    pub fn as_number(self) -> Option<f64> {
+        match self.kind() {
+            ValueKind::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(self) -> Option<ObjRef> {
+        match self.kind() {
+            ValueKind::Object(obj) => Some(obj),
+            _ => None,
+        }
+    }
+}
+
+impl From<ValueKind> for Value {
+    fn from(kind: ValueKind) -> Self {
+        match kind {
+            ValueKind::Nil => Value::NIL,
+            ValueKind::Boolean(b) => Value::boolean(b),
+            ValueKind::Number(n) => Value::number(n),
+            ValueKind::Object(obj) => Value::object(obj),
+        }
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.kind().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_kinds() {
+        let kinds = [
+            ValueKind::Nil,
+            ValueKind::Boolean(true),
+            ValueKind::Boolean(false),
+            ValueKind::Number(0.0),
+            ValueKind::Number(-0.0),
+            ValueKind::Number(1.5),
+            ValueKind::Number(f64::INFINITY),
+            ValueKind::Number(f64::NEG_INFINITY),
+            ValueKind::Number(f64::MAX),
+            ValueKind::Object(ObjRef(0)),
+            ValueKind::Object(ObjRef(u32::MAX)),
+        ];
+
+        for kind in kinds {
+            assert_eq!(Value::from(kind).kind(), kind);
+        }
+        assert!(Value::number(f64::NAN).as_number().unwrap().is_nan());
+        assert!(Value::number(-0.0).as_number().unwrap().is_sign_negative());
+    }
+
+    #[test]
+    fn nan_payloads_are_not_tags() {
+        // A NaN with arbitrary payload bits, such as one read from a compiled file, must not be
+        // mistaken for another kind of value.
+        let nan = f64::from_bits(0xfffc_0000_0000_0001);
+        assert!(nan.is_nan());
+        assert!(Value::number(nan).as_number().unwrap().is_nan());
+    }
+
+    #[test]
+    fn checks_truthiness() {
+        assert!(!Value::NIL.is_truthy());
+        assert!(!Value::boolean(false).is_truthy());
+        assert!(Value::boolean(true).is_truthy());
+        assert!(Value::number(0.0).is_truthy());
+        assert!(Value::object(ObjRef(0)).is_truthy());
+    }
+
+    #[cfg(feature = "nan-boxing")]
+    #[test]
+    fn packs_into_a_word() {
+        assert_eq!(std::mem::size_of::<Value>(), 8);
+    }
+}
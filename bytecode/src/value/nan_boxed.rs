@@ -0,0 +1,56 @@
+use super::ValueKind;
+use crate::object::ObjRef;
+
+/// Set in every quiet NaN along with the bit below the quiet bit, which is never set by
+/// arithmetic, so that values other than numbers can't collide with a NaN produced by the VM.
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+/// Distinguishes objects from the singleton values.
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+
+const TAG_NIL: u64 = 1;
+const TAG_FALSE: u64 = 2;
+const TAG_TRUE: u64 = 3;
+
+/// A runtime value packed into a single 64 bit word, objects live on the VM's `Heap`. Use
+/// `Heap::display` and `Heap::values_equal` to format and compare values.
+///
+/// Numbers are stored as their bits, every other value is a quiet NaN: nil and booleans are
+/// tagged in the low bits and objects set the sign bit with the handle in the low 32 bits.
+#[derive(Copy, Clone)]
+pub struct Value(u64);
+
+impl Value {
+    pub const NIL: Value = Value(QNAN | TAG_NIL);
+
+    pub fn boolean(b: bool) -> Value {
+        Value(QNAN | if b { TAG_TRUE } else { TAG_FALSE })
+    }
+
+    pub fn number(n: f64) -> Value {
+        // NaNs are canonicalized so that their payload can't be mistaken for a tag
+        if n.is_nan() {
+            Value(f64::NAN.to_bits())
+        } else {
+            Value(n.to_bits())
+        }
+    }
+
+    pub fn object(obj: ObjRef) -> Value {
+        Value(SIGN_BIT | QNAN | obj.0 as u64)
+    }
+
+    pub fn kind(self) -> ValueKind {
+        if self.0 & QNAN != QNAN {
+            ValueKind::Number(f64::from_bits(self.0))
+        } else if self.0 & SIGN_BIT != 0 {
+            ValueKind::Object(ObjRef(self.0 as u32))
+        } else {
+            match self.0 & !QNAN {
+                TAG_NIL => ValueKind::Nil,
+                TAG_FALSE => ValueKind::Boolean(false),
+                TAG_TRUE => ValueKind::Boolean(true),
+                _ => unreachable!("invalid value tag {:#x}", self.0),
+            }
+        }
+    }
+}
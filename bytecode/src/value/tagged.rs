@@ -0,0 +1,27 @@
+use super::ValueKind;
+use crate::object::ObjRef;
+
+/// A runtime value, objects live on the VM's `Heap`. Use `Heap::display` and
+/// `Heap::values_equal` to format and compare values.
+#[derive(Copy, Clone)]
+pub struct Value(ValueKind);
+
+impl Value {
+    pub const NIL: Value = Value(ValueKind::Nil);
+
+    pub fn boolean(b: bool) -> Value {
+        Value(ValueKind::Boolean(b))
+    }
+
+    pub fn number(n: f64) -> Value {
+        Value(ValueKind::Number(n))
+    }
+
+    pub fn object(obj: ObjRef) -> Value {
+        Value(ValueKind::Object(obj))
+    }
+
+    pub fn kind(self) -> ValueKind {
+        self.0
+    }
+}
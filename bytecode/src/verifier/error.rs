@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// An error found while verifying a function along with where it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    /// The function containing the error, formatted as `<fn name>` or `<script>`.
+    pub function: String,
+    /// Offset of the offending instruction in the function's chunk.
+    pub offset: usize,
+    pub kind: VerifyErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    InvalidInstruction,
+    ConstantOutOfRange {
+        index: usize,
+    },
+    WrongConstantType {
+        index: usize,
+        expected: &'static str,
+    },
+    InvalidJumpTarget {
+        target: isize,
+    },
+    SlotOutOfRange {
+        slot: u8,
+        depth: usize,
+    },
+    UpvalueOutOfRange {
+        index: u8,
+    },
+    StackUnderflow {
+        depth: usize,
+        needed: usize,
+    },
+    StackMismatch {
+        expected: usize,
+        found: usize,
+    },
+    FallsOffEnd,
+    /// An operand on the stack isn't the kind of object the instruction operates on, which is
+    /// only found once the instruction is executed.
+    WrongOperandType {
+        expected: &'static str,
+    },
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at offset {} in {}",
+            self.kind, self.offset, self.function
+        )
+    }
+}
+
+impl Error for VerifyError {}
+
+impl Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use VerifyErrorKind::*;
+
+        match self {
+            InvalidInstruction => f.write_str("invalid instruction"),
+            ConstantOutOfRange { index } => write!(f, "constant {} is out of range", index),
+            WrongConstantType { index, expected } => {
+                write!(f, "expected constant {} to be a {}", index, expected)
+            }
+            InvalidJumpTarget { target } => write!(f, "invalid jump target {}", target),
+            SlotOutOfRange { slot, depth } => write!(
+                f,
+                "slot {} is out of range of a stack of depth {}",
+                slot, depth
+            ),
+            UpvalueOutOfRange { index } => write!(f, "upvalue {} is out of range", index),
+            StackUnderflow { depth, needed } => write!(
+                f,
+                "stack underflow, {} values needed but the stack has depth {}",
+                needed, depth
+            ),
+            StackMismatch { expected, found } => write!(
+                f,
+                "stack depth {} differs from depth {} on another path",
+                found, expected
+            ),
+            FallsOffEnd => f.write_str("execution falls off the end of the chunk"),
+            WrongOperandType { expected } => write!(f, "expected operand to be a {}", expected),
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use crate::chunk::{Chunk, Constant};
+use crate::function::Function;
+use crate::opcode::OpCode;
+
+pub use error::{VerifyError, VerifyErrorKind};
+
+mod error;
+
+/// Verifies that `function` and every function nested within it can be executed safely by the
+/// VM.
+///
+/// Every instruction must decode and reference constants of the right type, jumps must land on
+/// instruction boundaries, local and upvalue operands must be in range and the depth of the
+/// stack must be the same on every path reaching an instruction. Execution must never fall off
+/// the end of a chunk.
+pub fn verify(function: &Function) -> Result<(), VerifyError> {
+    Verifier::new(function).verify()?;
+
+    for constant in function.chunk.constants() {
+        if let Constant::Function(function) = constant {
+            verify(function)?;
+        }
+    }
+
+    Ok(())
+}
+
+struct Verifier<'a> {
+    function: &'a Function,
+    chunk: &'a Chunk,
+    /// The depth of the stack, including the callee in slot 0, before the instruction at each
+    /// offset is executed, `None` for offsets which aren't known to be reachable.
+    depths: Vec<Option<usize>>,
+    /// Offsets of instructions whose successors have yet to be visited.
+    pending: Vec<usize>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(function: &'a Function) -> Self {
+        Self {
+            function,
+            chunk: &function.chunk,
+            depths: vec![None; function.chunk.len()],
+            pending: Vec::new(),
+        }
+    }
+
+    fn verify(mut self) -> Result<(), VerifyError> {
+        let boundaries = self.check_instructions()?;
+
+        // The arguments sit above the callee when the function is entered
+        self.enter(0, 0, self.function.arity as usize + 1)?;
+
+        while let Some(offset) = self.pending.pop() {
+            let depth = self.depths[offset].expect("pending instruction has no depth");
+            let (op, len) = self.chunk.decode_at(offset);
+            let next = offset + len;
+
+            let (pops, pushes) = stack_effect(op);
+            if depth < pops + 1 {
+                return Err(self.error(
+                    offset,
+                    VerifyErrorKind::StackUnderflow {
+                        depth,
+                        needed: pops + 1,
+                    },
+                ));
+            }
+
+            self.check_slots(offset, op, depth)?;
+
+            let depth = depth - pops + pushes;
+            let target = match op {
+                OpCode::Jump(o) | OpCode::JumpIfFalse(o) | OpCode::JumpIfTrue(o) => {
+                    Some(next as isize + o as isize)
+                }
+                OpCode::Loop(o) => Some(next as isize - o as isize),
+                _ => None,
+            };
+            if let Some(target) = target {
+                match usize::try_from(target) {
+                    Ok(target) if boundaries.get(target) == Some(&true) => {
+                        self.enter(offset, target, depth)?
+                    }
+                    _ => {
+                        return Err(
+                            self.error(offset, VerifyErrorKind::InvalidJumpTarget { target })
+                        )
+                    }
+                }
+            }
+
+            if !matches!(op, OpCode::Return | OpCode::Jump(_) | OpCode::Loop(_)) {
+                self.enter(offset, next, depth)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes every instruction in the chunk and checks its constant and upvalue operands,
+    /// returning a map of the offsets at which instructions start.
+    fn check_instructions(&self) -> Result<Vec<bool>, VerifyError> {
+        let mut boundaries = vec![false; self.chunk.len()];
+        let mut offset = 0;
+
+        while offset < self.chunk.len() {
+            let (op, len) = OpCode::decode(&self.chunk.code()[offset..])
+                .ok_or_else(|| self.error(offset, VerifyErrorKind::InvalidInstruction))?;
+            boundaries[offset] = true;
+
+            let constant = match op {
+                OpCode::Constant(c) => Some((c.into(), Expected::Value)),
+                OpCode::ConstantLong(c) => Some((c as usize, Expected::Value)),
+                OpCode::AddLocalConstant(_, c)
+                | OpCode::SubtractLocalConstant(_, c)
+                | OpCode::LessLocalConstant(_, c) => Some((c.into(), Expected::Value)),
+                OpCode::GetGlobal(c)
+                | OpCode::DefineGlobal(c)
+                | OpCode::SetGlobal(c)
+                | OpCode::GetProperty(c)
+                | OpCode::SetProperty(c)
+                | OpCode::GetSuper(c)
+                | OpCode::Class(c)
+                | OpCode::Method(c)
+                | OpCode::Invoke(c, _)
+                | OpCode::SuperInvoke(c, _) => Some((c as usize, Expected::Name)),
+                OpCode::Closure(c) => Some((c as usize, Expected::Function)),
+                _ => None,
+            };
+
+            if let Some((index, expected)) = constant {
+                let constant = self.chunk.constants().get(index).ok_or_else(|| {
+                    self.error(offset, VerifyErrorKind::ConstantOutOfRange { index })
+                })?;
+
+                if !expected.matches(constant) {
+                    return Err(self.error(
+                        offset,
+                        VerifyErrorKind::WrongConstantType {
+                            index,
+                            expected: expected.describe(),
+                        },
+                    ));
+                }
+            }
+
+            if let OpCode::GetUpvalue(index) | OpCode::SetUpvalue(index) = op {
+                self.check_upvalue(offset, index)?;
+            }
+
+            offset += len;
+        }
+
+        Ok(boundaries)
+    }
+
+    /// Checks that the locals referenced by `op` exist in a stack of `depth` values.
+    fn check_slots(&self, offset: usize, op: OpCode, depth: usize) -> Result<(), VerifyError> {
+        let check = |slot: u8| {
+            if (slot as usize) < depth {
+                Ok(())
+            } else {
+                Err(self.error(offset, VerifyErrorKind::SlotOutOfRange { slot, depth }))
+            }
+        };
+
+        match op {
+            OpCode::GetLocal(slot)
+            | OpCode::SetLocal(slot)
+            | OpCode::AddLocalConstant(slot, _)
+            | OpCode::SubtractLocalConstant(slot, _)
+            | OpCode::LessLocalConstant(slot, _) => check(slot),
+            OpCode::Closure(c) => match &self.chunk.constants()[c as usize] {
+                Constant::Function(function) => function.upvalues.iter().try_for_each(|upvalue| {
+                    if upvalue.is_local {
+                        check(upvalue.index)
+                    } else {
+                        self.check_upvalue(offset, upvalue.index)
+                    }
+                }),
+                _ => unreachable!("closure constants are checked to be functions"),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn check_upvalue(&self, offset: usize, index: u8) -> Result<(), VerifyError> {
+        if (index as usize) < self.function.upvalues.len() {
+            Ok(())
+        } else {
+            Err(self.error(offset, VerifyErrorKind::UpvalueOutOfRange { index }))
+        }
+    }
+
+    /// Records that the instruction at `target` is reached from `from` with a stack of `depth`,
+    /// queueing it to be visited if it hasn't been reached before.
+    fn enter(&mut self, from: usize, target: usize, depth: usize) -> Result<(), VerifyError> {
+        match self.depths.get(target) {
+            None => Err(self.error(from, VerifyErrorKind::FallsOffEnd)),
+            Some(None) => {
+                self.depths[target] = Some(depth);
+                self.pending.push(target);
+                Ok(())
+            }
+            Some(&Some(expected)) if expected != depth => Err(self.error(
+                target,
+                VerifyErrorKind::StackMismatch {
+                    expected,
+                    found: depth,
+                },
+            )),
+            Some(Some(_)) => Ok(()),
+        }
+    }
+
+    fn error(&self, offset: usize, kind: VerifyErrorKind) -> VerifyError {
+        VerifyError {
+            function: self.function.to_string(),
+            offset,
+            kind,
+        }
+    }
+}
+
+/// The type of constant expected by an instruction's operand.
+#[derive(Copy, Clone)]
+enum Expected {
+    /// A number or string loaded onto the stack.
+    Value,
+    /// The name of a variable, property or class.
+    Name,
+    /// A function prototype for a closure.
+    Function,
+}
+
+impl Expected {
+    fn matches(self, constant: &Constant) -> bool {
+        matches!(
+            (self, constant),
+            (Expected::Value, Constant::Number(_) | Constant::String(_))
+                | (Expected::Name, Constant::String(_))
+                | (Expected::Function, Constant::Function(_))
+        )
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Expected::Value => "number or string",
+            Expected::Name => "string",
+            Expected::Function => "function",
+        }
+    }
+}
+
+/// The number of values popped from and pushed to the stack by `op`.
+fn stack_effect(op: OpCode) -> (usize, usize) {
+    use OpCode::*;
+
+    match op {
+        Constant(_)
+        | ConstantLong(_)
+        | Nil
+        | True
+        | False
+        | GetLocal(_)
+        | GetGlobal(_)
+        | GetUpvalue(_)
+        | Closure(_)
+        | Class(_)
+        | AddLocalConstant(..)
+        | SubtractLocalConstant(..)
+        | LessLocalConstant(..) => (0, 1),
+        Pop | DefineGlobal(_) | Print | CloseUpvalue | Return => (1, 0),
+        SetLocal(_) | SetGlobal(_) | SetUpvalue(_) | JumpIfFalse(_) | JumpIfTrue(_)
+        | GetProperty(_) | Not | Negate => (1, 1),
+        Equal | Greater | Less | Add | Subtract | Multiply | Divide | SetProperty(_)
+        | GetSuper(_) => (2, 1),
+        // The subclass is popped leaving the superclass, the method is popped leaving the class
+        Inherit | Method(_) => (2, 1),
+        Call(args) | Invoke(_, args) => (args as usize + 1, 1),
+        // The superclass is popped along with the receiver and arguments
+        SuperInvoke(_, args) => (args as usize + 2, 1),
+        Jump(_) | Loop(_) => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use lox_syntax::Parser;
+
+    use super::*;
+    use crate::chunk;
+    use crate::compiler::Compiler;
+    use crate::function::UpvalueDescriptor;
+
+    fn compile(source: &str) -> Function {
+        let mut parser = Parser::new(source);
+        let statements = parser.parse();
+        assert!(parser.diagnostics().is_empty());
+
+        let mut compiler = Compiler::new(source);
+        let function = compiler.compile(&statements);
+        assert!(compiler.diagnostics().is_empty());
+        function
+    }
+
+    fn script(ops: &[OpCode], constants: Vec<Constant>) -> Function {
+        let mut function = Function::new("", 0);
+        for constant in constants {
+            function.chunk.push_constant(constant);
+        }
+        for &op in ops {
+            function.chunk.push_op(op, 1);
+        }
+        function
+    }
+
+    fn error(ops: &[OpCode], constants: Vec<Constant>) -> VerifyErrorKind {
+        verify(&script(ops, constants)).unwrap_err().kind
+    }
+
+    #[test]
+    fn accepts_compiled_examples() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../example");
+        for entry in fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            let function = compile(&fs::read_to_string(&path).unwrap());
+            assert_eq!(verify(&function), Ok(()), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn accepts_wide_operands() {
+        let source: String = (0..300).map(|i| format!("var a{} = {};", i, i)).collect();
+        let function = compile(&(source + "class A { m() {} } A().m(); print a299;"));
+        assert_eq!(verify(&function), Ok(()));
+    }
+
+    #[test]
+    fn checks_constants() {
+        use OpCode::*;
+
+        assert_eq!(
+            error(&[Constant(1), Return], vec![]),
+            VerifyErrorKind::ConstantOutOfRange { index: 1 }
+        );
+        assert_eq!(
+            error(&[GetGlobal(0), Return], vec![chunk::Constant::Number(1.0)]),
+            VerifyErrorKind::WrongConstantType {
+                index: 0,
+                expected: "string"
+            }
+        );
+    }
+
+    #[test]
+    fn checks_jumps() {
+        use OpCode::*;
+
+        // Jumps into the middle of the constant instruction
+        assert_eq!(
+            error(
+                &[Jump(1), Constant(0), Return],
+                vec![chunk::Constant::Number(1.0)]
+            ),
+            VerifyErrorKind::InvalidJumpTarget { target: 4 }
+        );
+        assert_eq!(
+            error(&[Nil, Loop(10), Return], vec![]),
+            VerifyErrorKind::InvalidJumpTarget { target: -6 }
+        );
+        assert_eq!(error(&[Nil, Pop], vec![]), VerifyErrorKind::FallsOffEnd);
+    }
+
+    #[test]
+    fn checks_slots() {
+        use OpCode::*;
+
+        assert_eq!(
+            error(&[GetLocal(1), Return], vec![]),
+            VerifyErrorKind::SlotOutOfRange { slot: 1, depth: 1 }
+        );
+        assert_eq!(
+            error(&[GetUpvalue(0), Return], vec![]),
+            VerifyErrorKind::UpvalueOutOfRange { index: 0 }
+        );
+
+        let mut inner = Function::new("inner", 0);
+        inner.upvalues.push(UpvalueDescriptor {
+            index: 3,
+            is_local: true,
+        });
+        inner.chunk.push_op(Nil, 1);
+        inner.chunk.push_op(Return, 1);
+        assert_eq!(
+            error(
+                &[Closure(0), Return],
+                vec![chunk::Constant::Function(inner.into())]
+            ),
+            VerifyErrorKind::SlotOutOfRange { slot: 3, depth: 1 }
+        );
+    }
+
+    #[test]
+    fn checks_stack_depth() {
+        use OpCode::*;
+
+        assert_eq!(
+            error(&[Add, Return], vec![]),
+            VerifyErrorKind::StackUnderflow {
+                depth: 1,
+                needed: 3
+            }
+        );
+
+        // The branches leave a different number of values on the stack
+        let err = verify(&script(&[True, JumpIfFalse(1), Nil, Pop, Return], vec![])).unwrap_err();
+        assert_eq!(err.offset, 5);
+        assert_eq!(
+            err.kind,
+            VerifyErrorKind::StackMismatch {
+                expected: 2,
+                found: 3
+            }
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use lox_syntax::diagnostic::Diagnostic;
+
+use crate::verifier::VerifyError;
+
+pub type RResult<T> = Result<T, RuntimeError>;
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    TypeError {
+        message: Cow<'static, str>,
+    },
+    DivisionByZero,
+    Undefined {
+        message: Cow<'static, str>,
+    },
+    InvalidBytecode(VerifyError),
+    /// The maximum call depth was exceeded, the trace of the error only holds the innermost
+    /// calls.
+    StackOverflow,
+}
+
+impl RuntimeError {
+    /// The stable code identifying the kind of error, shared with the equivalent errors raised by
+    /// the tree-walk interpreter.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeError::TypeError { .. } => "E0301",
+            RuntimeError::DivisionByZero => "E0302",
+            RuntimeError::Undefined { .. } => "E0303",
+            RuntimeError::StackOverflow => "E0304",
+            RuntimeError::InvalidBytecode(_) => "E0306",
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::TypeError { message } => f.write_str(message),
+            RuntimeError::DivisionByZero => f.write_str("division by zero"),
+            RuntimeError::Undefined { message } => f.write_str(message),
+            RuntimeError::InvalidBytecode(e) => write!(f, "invalid bytecode: {}", e),
+            RuntimeError::StackOverflow => f.write_str("stack overflow"),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+/// A `RuntimeError` along with the state of the call stack at the point it was raised.
+#[derive(Debug)]
+pub struct TracedError {
+    pub error: RuntimeError,
+    /// The active calls, innermost first.
+    pub trace: Vec<TraceFrame>,
+}
+
+/// The function and line which was executing in a call frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    /// The name of the function, empty for the top level script.
+    pub function: String,
+    pub line: u32,
+}
+
+impl Display for TracedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)?;
+        for frame in self.trace.iter() {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytecode only records the lines of instructions so the error is reported at the line of the
+/// innermost call and the trace as notes rather than spans.
+impl From<&TracedError> for Diagnostic {
+    fn from(error: &TracedError) -> Self {
+        let mut diagnostic =
+            Diagnostic::error(error.error.to_string()).with_code(error.error.code());
+        if let Some(frame) = error.trace.first() {
+            diagnostic = diagnostic.with_line(frame.line as usize);
+        }
+        let diagnostic = error.trace.iter().fold(diagnostic, |diagnostic, frame| {
+            diagnostic.with_note(frame.to_string())
+        });
+
+        match error.error {
+            RuntimeError::StackOverflow => {
+                diagnostic.with_help("check that recursive functions have a base case")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl Error for TracedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl Display for TraceFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.function.is_empty() {
+            write!(f, "[line {}] in script", self.line)
+        } else {
+            write!(f, "[line {}] in {}()", self.line, self.function)
+        }
+    }
+}
@@ -0,0 +1,1563 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::chunk::Constant;
+use crate::function::Function;
+use crate::heap::Heap;
+use crate::object::{
+    BoundMethod, CacheSite, Class, Closure, InlineCache, Instance, ObjFunction, ObjRef, Object,
+    Upvalue,
+};
+use crate::opcode::OpCode;
+use crate::value::{Value, ValueKind};
+use crate::verifier::{verify, VerifyError, VerifyErrorKind};
+
+pub use error::{RResult, RuntimeError, TraceFrame, TracedError};
+
+mod error;
+mod native;
+
+/// The number of nested calls allowed by default before a `StackOverflow` is raised, the same as
+/// the tree-walk interpreter so that both overflow at the same depth.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// The number of innermost calls included in the trace of a `StackOverflow`.
+const STACK_OVERFLOW_TRACE_LEN: usize = 8;
+
+/// A stack based virtual machine which executes the `Function`s produced by the `Compiler`.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    /// Global variables keyed by their interned name.
+    globals: HashMap<ObjRef, Value>,
+    /// Upvalues which still point into the stack, ordered by the slot they capture.
+    open_upvalues: Vec<ObjRef>,
+    /// The interned name of initializers, looked up whenever a class is called.
+    init_string: ObjRef,
+    heap: Heap,
+    out: Box<dyn Write>,
+    cache_stats: CacheStats,
+    max_call_depth: usize,
+    /// Print the stack and each instruction before it is executed.
+    #[cfg(feature = "trace")]
+    trace_execution: bool,
+}
+
+/// Counts of the method lookups made through the inline caches of `GetProperty` and `Invoke`
+/// instructions. Accessing a field doesn't look up a method so is counted as neither.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups which found the method for the receiver's class in the cache.
+    pub hits: u64,
+    /// Lookups which had to search the class's methods, replacing the cached method.
+    pub misses: u64,
+}
+
+/// The activation record of a function call.
+struct CallFrame {
+    closure: ObjRef,
+    function: Rc<Function>,
+    constants: Rc<[Value]>,
+    caches: Rc<[CacheSite]>,
+    /// Index of the next instruction to execute in the function's chunk.
+    ip: usize,
+    /// Index of the frame's first slot in the value stack, slot 0 holds the callee.
+    slots: usize,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_output(io::stdout())
+    }
+
+    /// Creates a VM which writes the output of `print` statements to `out`.
+    pub fn with_output(out: impl Write + 'static) -> Self {
+        let mut heap = Heap::new();
+        let init_string = heap.intern("init");
+
+        let mut vm = Self {
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(64),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            init_string,
+            heap,
+            out: Box::new(out),
+            cache_stats: CacheStats::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            #[cfg(feature = "trace")]
+            trace_execution: false,
+        };
+
+        for native in native::NATIVES {
+            let obj = vm.heap.alloc(Object::Native(*native));
+            let name = vm.heap.intern(native.name);
+            vm.globals.insert(name, Value::object(obj));
+        }
+
+        vm
+    }
+
+    /// Verifies and runs `script`, if a runtime error occurs it is returned along with a trace of
+    /// the calls which were active at the time.
+    pub fn interpret(&mut self, script: Function) -> Result<(), TracedError> {
+        verify(&script).map_err(|e| TracedError {
+            error: RuntimeError::InvalidBytecode(e),
+            trace: Vec::new(),
+        })?;
+
+        let function = self.load_function(Rc::new(script));
+        // The script isn't reachable from the roots until its closure is on the stack so it is
+        // allocated without giving the collector a chance to run.
+        let closure = self.heap.alloc(Object::Closure(Closure {
+            function,
+            upvalues: Vec::new(),
+        }));
+        self.push(Value::object(closure));
+
+        self.call(closure, 0)
+            .and_then(|_| self.run())
+            .map_err(|error| {
+                let mut trace = self.trace();
+                if matches!(error, RuntimeError::StackOverflow) {
+                    trace.truncate(STACK_OVERFLOW_TRACE_LEN);
+                }
+
+                self.stack.clear();
+                self.frames.clear();
+                self.open_upvalues.clear();
+                TracedError { error, trace }
+            })
+    }
+
+    /// Captures the function and current line of each active call frame, innermost first.
+    fn trace(&self) -> Vec<TraceFrame> {
+        self.frames
+            .iter()
+            .rev()
+            .map(|frame| TraceFrame {
+                function: frame.function.name.clone(),
+                // ip has already moved past the instruction being executed
+                line: frame.function.chunk.line(frame.ip.saturating_sub(1)),
+            })
+            .collect()
+    }
+
+    /// Allocates `function` and all of the functions nested within it on the heap, converting
+    /// their constant pools into runtime values. The collector isn't run while loading as the
+    /// objects aren't reachable from the roots until the script is called.
+    fn load_function(&mut self, function: Rc<Function>) -> ObjRef {
+        let constants = function
+            .chunk
+            .constants()
+            .iter()
+            .map(|constant| match constant {
+                Constant::Number(n) => Value::number(*n),
+                Constant::String(s) => Value::object(self.heap.intern(s)),
+                Constant::Function(f) => Value::object(self.load_function(f.clone())),
+            })
+            .collect();
+
+        let caches = function
+            .chunk
+            .instructions()
+            .filter(|(_, op)| matches!(op, OpCode::GetProperty(_) | OpCode::Invoke(..)))
+            .map(|(offset, _)| CacheSite {
+                offset,
+                cache: Cell::new(None),
+            })
+            .collect();
+
+        self.heap.alloc(Object::Function(ObjFunction {
+            function,
+            constants,
+            caches,
+        }))
+    }
+
+    fn run(&mut self) -> RResult<()> {
+        loop {
+            #[cfg(feature = "trace")]
+            if self.trace_execution {
+                self.trace_instruction();
+            }
+
+            let frame = self.frames.last_mut().expect("no active call frame");
+            let offset = frame.ip;
+            let (op, len) = frame.function.chunk.decode_at(offset);
+            frame.ip += len;
+
+            match op {
+                OpCode::Constant(c) => {
+                    let constant = self.constant(c.into());
+                    self.push(constant);
+                }
+                OpCode::ConstantLong(c) => {
+                    let constant = self.constant(c as usize);
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::NIL),
+                OpCode::True => self.push(Value::boolean(true)),
+                OpCode::False => self.push(Value::boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal(slot) => {
+                    let value = self.stack[self.frame().slots + slot as usize];
+                    self.push(value);
+                }
+                OpCode::SetLocal(slot) => {
+                    let index = self.frame().slots + slot as usize;
+                    self.stack[index] = self.peek(0);
+                }
+                OpCode::GetGlobal(c) => {
+                    let name = self.identifier(c);
+                    let value = self.globals.get(&name).copied();
+                    match value {
+                        Some(value) => self.push(value),
+                        None => {
+                            return Err(RuntimeError::Undefined {
+                                message: format!("undefined variable {}", self.heap.string(name))
+                                    .into(),
+                            })
+                        }
+                    }
+                }
+                OpCode::DefineGlobal(c) => {
+                    let name = self.identifier(c);
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal(c) => {
+                    let name = self.identifier(c);
+                    let value = self.peek(0);
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => {
+                            return Err(RuntimeError::Undefined {
+                                message: format!(
+                                    "cannot assign undefined variable {}",
+                                    self.heap.string(name)
+                                )
+                                .into(),
+                            })
+                        }
+                    }
+                }
+                OpCode::GetUpvalue(index) => {
+                    let value = match self.heap.get(self.upvalue(index)) {
+                        Object::Upvalue(Upvalue::Open(slot)) => self.stack[*slot],
+                        Object::Upvalue(Upvalue::Closed(value)) => *value,
+                        object => panic!("expected upvalue found {:?}", object),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue(index) => {
+                    let value = self.peek(0);
+                    match self.heap.get_mut(self.upvalue(index)) {
+                        Object::Upvalue(Upvalue::Open(slot)) => {
+                            let slot = *slot;
+                            self.stack[slot] = value;
+                        }
+                        Object::Upvalue(Upvalue::Closed(closed)) => *closed = value,
+                        object => panic!("expected upvalue found {:?}", object),
+                    }
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::GetProperty(c) => {
+                    let name = self.identifier(c);
+                    let instance = self.instance(self.peek(0))?;
+                    let field = self
+                        .heap
+                        .get(instance)
+                        .as_instance()
+                        .fields
+                        .get(&name)
+                        .copied();
+
+                    match field {
+                        Some(value) => {
+                            self.pop();
+                            self.push(value);
+                        }
+                        None => {
+                            let class = self.heap.get(instance).as_instance().class;
+                            let method = self.cached_method(offset, class, name)?;
+                            self.bind_method(method);
+                        }
+                    }
+                }
+                OpCode::SetProperty(c) => {
+                    let name = self.identifier(c);
+                    let instance = self.instance(self.peek(1))?;
+                    let value = self.pop();
+                    self.heap
+                        .get_mut(instance)
+                        .as_instance_mut()
+                        .fields
+                        .insert(name, value);
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper(c) => {
+                    let name = self.identifier(c);
+                    let superclass = self.pop();
+                    let superclass = self.class_operand(superclass, offset)?;
+                    let method = self.find_method(superclass, name)?;
+                    self.bind_method(method);
+                }
+                OpCode::Class(c) => {
+                    let name = self.identifier(c);
+                    let class = self.alloc(Object::Class(Class {
+                        name,
+                        methods: HashMap::new(),
+                    }));
+                    self.push(Value::object(class));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1).as_object().map(|obj| self.heap.get(obj)) {
+                        Some(Object::Class(superclass)) => superclass.methods.clone(),
+                        _ => {
+                            return Err(RuntimeError::TypeError {
+                                message: "superclass must be a class".into(),
+                            })
+                        }
+                    };
+
+                    // Classes can't be modified once declared so the inherited methods are
+                    // copied down into the subclass rather than looked up at each call.
+                    let subclass = self.pop();
+                    let subclass = self.class_operand(subclass, offset)?;
+                    self.heap
+                        .get_mut(subclass)
+                        .as_class_mut()
+                        .methods
+                        .extend(superclass);
+                }
+                OpCode::Method(c) => {
+                    let name = self.identifier(c);
+                    let method = self.pop();
+                    let method = self.object_operand(method, offset, "closure", |object| {
+                        matches!(object, Object::Closure(_))
+                    })?;
+                    let class = self.class_operand(self.peek(0), offset)?;
+                    self.heap
+                        .get_mut(class)
+                        .as_class_mut()
+                        .methods
+                        .insert(name, method);
+                }
+                OpCode::Invoke(c, arg_count) => {
+                    let name = self.identifier(c);
+                    self.invoke(offset, name, arg_count)?;
+                }
+                OpCode::SuperInvoke(c, arg_count) => {
+                    let name = self.identifier(c);
+                    let superclass = self.pop();
+                    let superclass = self.class_operand(superclass, offset)?;
+                    self.invoke_from_class(superclass, name, arg_count)?;
+                }
+                OpCode::Equal => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    self.push(Value::boolean(self.heap.values_equal(l, r)));
+                }
+                OpCode::Greater => self.binary_op(">", |l, r| Ok(Value::boolean(l > r)))?,
+                OpCode::Less => self.less()?,
+                OpCode::Add => self.add()?,
+                OpCode::Subtract => self.subtract()?,
+                OpCode::Multiply => self.binary_op("*", |l, r| Ok(Value::number(l * r)))?,
+                OpCode::Divide => self.binary_op("/", |l, r| {
+                    if r == 0f64 {
+                        Err(RuntimeError::DivisionByZero)
+                    } else {
+                        Ok(Value::number(l / r))
+                    }
+                })?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::boolean(!value.is_truthy()));
+                }
+                OpCode::Negate => {
+                    let value = self.pop();
+                    match value.as_number() {
+                        Some(n) => self.push(Value::number(-n)),
+                        None => {
+                            return Err(RuntimeError::TypeError {
+                                message: format!(
+                                    "Expected number found {}",
+                                    self.heap.display(value)
+                                )
+                                .into(),
+                            })
+                        }
+                    }
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    // Failing to write to the output isn't something a Lox program can observe
+                    let _ = writeln!(self.out, "{}", self.heap.display(value));
+                }
+                OpCode::Jump(offset) => self.frame_mut().ip += offset as usize,
+                OpCode::JumpIfFalse(offset) => {
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset as usize;
+                    }
+                }
+                OpCode::JumpIfTrue(offset) => {
+                    if self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset as usize;
+                    }
+                }
+                OpCode::Loop(offset) => self.frame_mut().ip -= offset as usize,
+                OpCode::AddLocalConstant(slot, c) => match self.local_constant_operands(slot, c) {
+                    Some((l, r)) => self.push(Value::number(l + r)),
+                    None => self.add()?,
+                },
+                OpCode::SubtractLocalConstant(slot, c) => {
+                    match self.local_constant_operands(slot, c) {
+                        Some((l, r)) => self.push(Value::number(l - r)),
+                        None => self.subtract()?,
+                    }
+                }
+                OpCode::LessLocalConstant(slot, c) => match self.local_constant_operands(slot, c) {
+                    Some((l, r)) => self.push(Value::boolean(l < r)),
+                    None => self.less()?,
+                },
+                OpCode::Call(arg_count) => self.call_value(arg_count)?,
+                OpCode::Closure(c) => {
+                    let constant = self.constant(c as usize);
+                    let function = match constant.as_object() {
+                        Some(function) => function,
+                        None => panic!(
+                            "expected function constant found {}",
+                            self.heap.display(constant)
+                        ),
+                    };
+
+                    let prototype = self.function(function).function.clone();
+                    let mut upvalues = Vec::with_capacity(prototype.upvalues.len());
+                    for descriptor in prototype.upvalues.iter() {
+                        let upvalue = if descriptor.is_local {
+                            self.capture_upvalue(self.frame().slots + descriptor.index as usize)
+                        } else {
+                            self.upvalue(descriptor.index)
+                        };
+                        upvalues.push(upvalue);
+                    }
+
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }));
+                    self.push(Value::object(closure));
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("no active call frame");
+                    self.close_upvalues(frame.slots);
+
+                    if self.frames.is_empty() {
+                        // pop the script
+                        self.pop();
+                        return Ok(());
+                    }
+
+                    self.stack.truncate(frame.slots);
+                    self.push(result);
+                }
+            }
+        }
+    }
+
+    fn call_value(&mut self, arg_count: u8) -> RResult<()> {
+        if let Some(callee) = self.peek(arg_count as usize).as_object() {
+            match self.heap.get(callee) {
+                Object::Closure(_) => return self.call(callee, arg_count),
+                Object::Native(native) => {
+                    check_arity(native.arity, arg_count)?;
+
+                    let args_start = self.stack.len() - arg_count as usize;
+                    let result = (native.function)(&self.stack[args_start..])?;
+                    self.stack.truncate(args_start - 1);
+                    self.push(result);
+
+                    return Ok(());
+                }
+                Object::Class(class) => {
+                    let initializer = class.methods.get(&self.init_string).copied();
+                    let instance = self.alloc(Object::Instance(Instance {
+                        class: callee,
+                        fields: HashMap::new(),
+                    }));
+
+                    // The instance replaces the class as the receiver of the initializer
+                    let slot = self.stack.len() - arg_count as usize - 1;
+                    self.stack[slot] = Value::object(instance);
+
+                    return match initializer {
+                        Some(initializer) => self.call(initializer, arg_count),
+                        None => check_arity(0, arg_count),
+                    };
+                }
+                Object::BoundMethod(bound) => {
+                    let method = bound.method;
+                    let slot = self.stack.len() - arg_count as usize - 1;
+                    self.stack[slot] = bound.receiver;
+                    return self.call(method, arg_count);
+                }
+                _ => {}
+            }
+        }
+
+        Err(RuntimeError::TypeError {
+            message: "can only call functions and classes".into(),
+        })
+    }
+
+    /// Calls the method `name` on the receiver below the arguments, fields holding functions are
+    /// called as well as methods. The method is looked up through the cache of the `Invoke` at
+    /// `site`.
+    fn invoke(&mut self, site: usize, name: ObjRef, arg_count: u8) -> RResult<()> {
+        let instance = self.instance(self.peek(arg_count as usize))?;
+        let instance = self.heap.get(instance).as_instance();
+
+        match instance.fields.get(&name).copied() {
+            Some(field) => {
+                let slot = self.stack.len() - arg_count as usize - 1;
+                self.stack[slot] = field;
+                self.call_value(arg_count)
+            }
+            None => {
+                let method = self.cached_method(site, instance.class, name)?;
+                self.call(method, arg_count)
+            }
+        }
+    }
+
+    fn invoke_from_class(&mut self, class: ObjRef, name: ObjRef, arg_count: u8) -> RResult<()> {
+        let method = self.find_method(class, name)?;
+        self.call(method, arg_count)
+    }
+
+    /// Replaces the receiver on top of the stack with `method` bound to it.
+    fn bind_method(&mut self, method: ObjRef) {
+        let bound = self.alloc(Object::BoundMethod(BoundMethod {
+            receiver: self.peek(0),
+            method,
+        }));
+        self.pop();
+        self.push(Value::object(bound));
+    }
+
+    /// Finds the method `name` of `class` using the inline cache of the instruction at `site` in
+    /// the current frame, a miss replaces the cached method so that monomorphic sites only search
+    /// the class once.
+    fn cached_method(&mut self, site: usize, class: ObjRef, name: ObjRef) -> RResult<ObjRef> {
+        let caches = &self.frame().caches;
+        let index = caches
+            .binary_search_by_key(&site, |cache| cache.offset)
+            .expect("instruction has no inline cache");
+        if let Some(cached) = caches[index].cache.get().filter(|c| c.class == class) {
+            self.cache_stats.hits += 1;
+            return Ok(cached.method);
+        }
+
+        self.cache_stats.misses += 1;
+        let method = self.find_method(class, name)?;
+        self.frame().caches[index]
+            .cache
+            .set(Some(InlineCache { class, method }));
+        Ok(method)
+    }
+
+    fn find_method(&self, class: ObjRef, name: ObjRef) -> RResult<ObjRef> {
+        self.heap
+            .get(class)
+            .as_class()
+            .methods
+            .get(&name)
+            .copied()
+            .ok_or_else(|| RuntimeError::Undefined {
+                message: format!("undefined property {}", self.heap.string(name)).into(),
+            })
+    }
+
+    /// Returns the instance referenced by `value`, only instances have properties.
+    fn instance(&self, value: Value) -> RResult<ObjRef> {
+        match value.as_object() {
+            Some(obj) if matches!(self.heap.get(obj), Object::Instance(_)) => Ok(obj),
+            _ => Err(RuntimeError::TypeError {
+                message: "only instances have properties".into(),
+            }),
+        }
+    }
+
+    fn call(&mut self, closure: ObjRef, arg_count: u8) -> RResult<()> {
+        let function = match self.heap.get(closure) {
+            Object::Closure(closure) => self.function(closure.function),
+            object => panic!("expected closure found {:?}", object),
+        };
+        check_arity(function.function.arity, arg_count)?;
+
+        // The frame of the script isn't counted as a call
+        if self.frames.len() > self.max_call_depth {
+            return Err(RuntimeError::StackOverflow);
+        }
+
+        let frame = CallFrame {
+            closure,
+            function: function.function.clone(),
+            constants: function.constants.clone(),
+            caches: function.caches.clone(),
+            ip: 0,
+            slots: self.stack.len() - arg_count as usize - 1,
+        };
+        self.frames.push(frame);
+
+        Ok(())
+    }
+
+    /// Returns the upvalue capturing `slot`, reusing an open upvalue if the slot has already been
+    /// captured so that every closure over a variable shares it.
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let position = self
+            .open_upvalues
+            .partition_point(|&upvalue| self.open_slot(upvalue) < slot);
+
+        if let Some(&upvalue) = self.open_upvalues.get(position) {
+            if self.open_slot(upvalue) == slot {
+                return upvalue;
+            }
+        }
+
+        let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.insert(position, upvalue);
+        upvalue
+    }
+
+    /// Closes every open upvalue capturing a slot at or above `last`, moving the captured
+    /// variables off the stack.
+    fn close_upvalues(&mut self, last: usize) {
+        while let Some(&upvalue) = self.open_upvalues.last() {
+            let slot = self.open_slot(upvalue);
+            if slot < last {
+                break;
+            }
+
+            *self.heap.get_mut(upvalue) = Object::Upvalue(Upvalue::Closed(self.stack[slot]));
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn open_slot(&self, upvalue: ObjRef) -> usize {
+        match self.heap.get(upvalue) {
+            Object::Upvalue(Upvalue::Open(slot)) => *slot,
+            object => panic!("expected open upvalue found {:?}", object),
+        }
+    }
+
+    /// Allocates `object`, collecting garbage first if the heap has grown past its threshold.
+    ///
+    /// Any objects referenced by `object` must be reachable from the roots.
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+
+        self.heap.alloc(object)
+    }
+
+    /// Interns `string`, collecting garbage first if the heap has grown past its threshold.
+    fn intern(&mut self, string: &str) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+
+        self.heap.intern(string)
+    }
+
+    /// Frees every object which isn't reachable from the value stack, the globals, the active
+    /// call frames or the open upvalues.
+    pub fn collect_garbage(&mut self) {
+        for &value in self.stack.iter() {
+            self.heap.mark_value(value);
+        }
+
+        for (&name, &value) in self.globals.iter() {
+            self.heap.mark_object(name);
+            self.heap.mark_value(value);
+        }
+
+        for frame in self.frames.iter() {
+            self.heap.mark_object(frame.closure);
+        }
+
+        for &upvalue in self.open_upvalues.iter() {
+            self.heap.mark_object(upvalue);
+        }
+
+        self.heap.mark_object(self.init_string);
+
+        self.heap.collect();
+    }
+
+    /// Sets the number of nested calls allowed before a `StackOverflow` is raised.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// The number of inline cache hits and misses since the VM was created.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache_stats
+    }
+
+    /// Prints the contents of the stack and each instruction before it is executed to the VM's
+    /// output.
+    #[cfg(feature = "trace")]
+    pub fn set_trace_execution(&mut self, enabled: bool) {
+        self.trace_execution = enabled;
+    }
+
+    #[cfg(feature = "trace")]
+    fn trace_instruction(&mut self) {
+        let frame = self.frame();
+        let mut trace = String::from("          ");
+        for &value in self.stack.iter() {
+            trace.push_str(&format!("[ {} ]", self.heap.display(value)));
+        }
+        trace.push('\n');
+        trace.push_str(
+            &frame
+                .function
+                .chunk
+                .disassemble_instruction(frame.ip)
+                .to_string(),
+        );
+
+        let _ = self.out.write_all(trace.as_bytes());
+    }
+
+    /// Runs a collection before every allocation, used to check that every live object is
+    /// reachable from the roots.
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    /// Adds the two values on top of the stack, which must both be numbers or both be strings.
+    fn add(&mut self) -> RResult<()> {
+        match (self.peek(1).kind(), self.peek(0).kind()) {
+            (ValueKind::Object(l), ValueKind::Object(r))
+                if matches!(
+                    (self.heap.get(l), self.heap.get(r)),
+                    (Object::String(_), Object::String(_))
+                ) =>
+            {
+                let string = format!("{}{}", self.heap.string(l), self.heap.string(r));
+                let string = self.intern(&string);
+                self.pop();
+                self.pop();
+                self.push(Value::object(string));
+                Ok(())
+            }
+            _ => self.binary_op("+", |l, r| Ok(Value::number(l + r))),
+        }
+    }
+
+    fn subtract(&mut self) -> RResult<()> {
+        self.binary_op("-", |l, r| Ok(Value::number(l - r)))
+    }
+
+    fn less(&mut self) -> RResult<()> {
+        self.binary_op("<", |l, r| Ok(Value::boolean(l < r)))
+    }
+
+    /// Loads the operands of a local and constant superinstruction. If they are both numbers they
+    /// are returned for the instruction to operate on directly, otherwise they are pushed onto the
+    /// stack to be handled the same as the unfused instructions.
+    fn local_constant_operands(&mut self, slot: u8, c: u8) -> Option<(f64, f64)> {
+        let local = self.stack[self.frame().slots + slot as usize];
+        let constant = self.constant(c.into());
+
+        match (local.as_number(), constant.as_number()) {
+            (Some(l), Some(r)) => Some((l, r)),
+            _ => {
+                self.push(local);
+                self.push(constant);
+                None
+            }
+        }
+    }
+
+    /// Applies `f` to the two numbers on top of the stack, replacing them with the result.
+    fn binary_op<F>(&mut self, op: &str, f: F) -> RResult<()>
+    where
+        F: FnOnce(f64, f64) -> RResult<Value>,
+    {
+        match (self.peek(1).as_number(), self.peek(0).as_number()) {
+            (Some(l), Some(r)) => {
+                let result = f(l, r)?;
+                self.pop();
+                self.pop();
+                self.push(result);
+                Ok(())
+            }
+            _ => Err(RuntimeError::TypeError {
+                message: format!(
+                    "Illegal operation {} {} {}",
+                    self.heap.display(self.peek(1)),
+                    op,
+                    self.heap.display(self.peek(0))
+                )
+                .into(),
+            }),
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("no active call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("no active call frame")
+    }
+
+    fn constant(&self, index: usize) -> Value {
+        self.frame().constants[index]
+    }
+
+    fn identifier(&self, index: u32) -> ObjRef {
+        let constant = self.constant(index as usize);
+        match constant.as_object() {
+            Some(name) => name,
+            None => panic!(
+                "expected identifier constant found {}",
+                self.heap.display(constant)
+            ),
+        }
+    }
+
+    /// The upvalue at `index` in the closure of the current frame.
+    fn upvalue(&self, index: u8) -> ObjRef {
+        match self.heap.get(self.frame().closure) {
+            Object::Closure(closure) => closure.upvalues[index as usize],
+            object => panic!("expected closure found {:?}", object),
+        }
+    }
+
+    fn function(&self, function: ObjRef) -> &ObjFunction {
+        match self.heap.get(function) {
+            Object::Function(function) => function,
+            object => panic!("expected function found {:?}", object),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("value stack underflow")
+    }
+
+    /// Returns the object referenced by `value`, an operand of the instruction at `offset`,
+    /// if `is_expected` holds for it. The compiler only emits instructions on operands of the
+    /// right kind but the verifier doesn't track the kinds of values so a loaded chunk may not.
+    fn object_operand(
+        &self,
+        value: Value,
+        offset: usize,
+        expected: &'static str,
+        is_expected: fn(&Object) -> bool,
+    ) -> RResult<ObjRef> {
+        match value.as_object() {
+            Some(obj) if is_expected(self.heap.get(obj)) => Ok(obj),
+            _ => Err(RuntimeError::InvalidBytecode(VerifyError {
+                function: self.frame().function.to_string(),
+                offset,
+                kind: VerifyErrorKind::WrongOperandType { expected },
+            })),
+        }
+    }
+
+    fn class_operand(&self, value: Value, offset: usize) -> RResult<ObjRef> {
+        self.object_operand(value, offset, "class", |object| {
+            matches!(object, Object::Class(_))
+        })
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn check_arity(arity: u8, arg_count: u8) -> RResult<()> {
+    if arity == arg_count {
+        Ok(())
+    } else {
+        Err(RuntimeError::TypeError {
+            message: format!("expected {} arguments but got {}", arity, arg_count).into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use lox_syntax::Parser;
+
+    use super::*;
+    use crate::compiler::Compiler;
+
+    /// Output buffer which can be inspected after being handed to the VM.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn compile_with(source: &str, peephole: bool) -> Function {
+        let mut parser = Parser::new(source);
+        let statements = parser.parse();
+        assert!(parser.diagnostics().is_empty());
+
+        let mut compiler = Compiler::new(source);
+        compiler.set_peephole(peephole);
+        let function = compiler.compile(&statements);
+        assert!(compiler.diagnostics().is_empty());
+        function
+    }
+
+    fn compile(source: &str) -> Function {
+        compile_with(source, true)
+    }
+
+    fn run_vm(vm: &mut Vm, output: &Output, script: Function) -> (String, Result<(), TracedError>) {
+        let result = vm.interpret(script);
+        let output = String::from_utf8(output.0.take()).unwrap();
+        (output, result)
+    }
+
+    /// Runs `source`, the script is run again with the GC in stress mode and without the peephole
+    /// optimizer to check that neither changes its behaviour.
+    fn run(source: &str) -> (String, Result<(), TracedError>) {
+        let output = Output::default();
+        let (expected, result) = run_vm(
+            &mut Vm::with_output(output.clone()),
+            &output,
+            compile(source),
+        );
+
+        let mut vm = Vm::with_output(output.clone());
+        vm.set_gc_stress(true);
+        let stressed = run_vm(&mut vm, &output, compile(source));
+
+        let unoptimized = run_vm(
+            &mut Vm::with_output(output.clone()),
+            &output,
+            compile_with(source, false),
+        );
+
+        for (other, other_result) in [stressed, unoptimized] {
+            assert_eq!(other, expected);
+            assert_eq!(
+                other_result.as_ref().map_err(ToString::to_string),
+                result.as_ref().map_err(ToString::to_string)
+            );
+        }
+
+        (expected, result)
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let (output, result) = run(r#"
+            print 1 + 2 * 3 - 4 / 2;
+            print -(1 + 1);
+            print !nil;
+            print 1 <= 2 and 2 != 3;
+            print nil or "default";
+            print "con" + "cat";
+            print "con" + "cat" == "concat";
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "5\n-2\ntrue\ntrue\n\"default\"\n\"concat\"\ntrue\n");
+    }
+
+    #[test]
+    fn loads_wide_constants() {
+        let terms: String = (1..=300).map(|i| format!("sum = sum + {};", i)).collect();
+        let (output, result) = run(&format!("var sum = 0; {} print sum;", terms));
+
+        assert!(result.is_ok());
+        assert_eq!(output, "45150\n");
+    }
+
+    #[test]
+    fn uses_wide_name_operands() {
+        let globals: String = (0..300).map(|i| format!("var a{} = {};", i, i)).collect();
+        let (output, result) = run(&format!(
+            r#"
+            {}
+            class Point {{ init(x) {{ this.x = x; }} get() {{ return this.x; }} }}
+            fun add(a, b) {{ return a + b; }}
+            a299 = a299 + 1;
+            var p = Point(a128);
+            p.y = 2;
+            print add(a0, a299);
+            print p.get();
+            print p.y;
+        "#,
+            globals
+        ));
+
+        assert!(result.is_ok());
+        assert_eq!(output, "300\n128\n2\n");
+    }
+
+    #[test]
+    fn scopes_globals_and_locals() {
+        let (output, result) = run(r#"
+            var a = "global";
+            {
+                var a = "outer";
+                {
+                    var a = "inner";
+                    print a;
+                }
+                print a;
+            }
+            print a;
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "\"inner\"\n\"outer\"\n\"global\"\n");
+    }
+
+    #[test]
+    fn executes_control_flow() {
+        let (output, result) = run(r#"
+            var sum = 0;
+            for (var i = 0; i < 5; i = i + 1) {
+                if (i == 2) sum = sum + 10; else sum = sum + i;
+            }
+            print sum;
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "18\n");
+    }
+
+    #[test]
+    fn breaks_and_continues_loops() {
+        let (output, result) = run(r#"
+            for (var i = 0; i < 10; i = i + 1) {
+                if (i == 1) continue;
+                var square = i * i;
+                if (square > 10) break;
+                print square;
+            }
+
+            var closure = nil;
+            while (true) {
+                var captured = "captured";
+                fun f() { print captured; }
+                closure = f;
+                break;
+            }
+            closure();
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "0\n4\n9\n\"captured\"\n");
+    }
+
+    #[test]
+    fn calls_functions() {
+        let (output, result) = run(r#"
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            print fib(15);
+            print fib;
+            print clock;
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "610\n<fn fib>\n<fn clock>\n");
+    }
+
+    #[test]
+    fn calls_lambdas() {
+        let (output, result) = run(r#"
+            fun adder(n) {
+                return fun (x) { return x + n; };
+            }
+            print adder(1)(2);
+            print fun (a, b) { return a * b; }(3, 4);
+            print fun () {};
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "3\n12\n<fn lambda>\n");
+    }
+
+    #[test]
+    fn captures_closures() {
+        let (output, result) = run(r#"
+            fun counter() {
+                var count = 0;
+                fun increment() {
+                    count = count + 1;
+                    return count;
+                }
+                return increment;
+            }
+
+            var a = counter();
+            var b = counter();
+            print a();
+            print a();
+            print b();
+
+            var get;
+            var set;
+            {
+                var shared = "before";
+                fun g() { return shared; }
+                fun s(value) { shared = value; }
+                get = g;
+                set = s;
+                shared = "during";
+                print get();
+            }
+            set("after");
+            print get();
+
+            fun outer() {
+                var x = "outer";
+                fun middle() {
+                    fun inner() { return x; }
+                    return inner;
+                }
+                return middle;
+            }
+            print outer()()();
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "1\n2\n1\n\"during\"\n\"after\"\n\"outer\"\n");
+    }
+
+    #[test]
+    fn closes_upvalues_at_end_of_scope() {
+        let (output, result) = run(r#"
+            var closures = nil;
+            for (var i = 0; i < 3; i = i + 1) {
+                var j = i;
+                fun f() { return j; }
+                if (i == 1) closures = f;
+            }
+            print closures();
+
+            var a = "global";
+            {
+                fun show() { print a; }
+                show();
+                var a = "block";
+                show();
+            }
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(output, "1\n\"global\"\n\"global\"\n");
+    }
+
+    #[test]
+    fn runs_classes() {
+        let (output, result) = run(r#"
+            class Animal {
+                init(name) {
+                    this.name = name;
+                }
+
+                speak() {
+                    return this.name + " makes a sound";
+                }
+            }
+
+            class Dog < Animal {
+                init(name) {
+                    super.init(name);
+                    this.tricks = 0;
+                }
+
+                speak() {
+                    return super.speak() + ", woof";
+                }
+            }
+
+            var dog = Dog("rex");
+            print dog.speak();
+            print dog;
+            print Dog;
+
+            var speak = dog.speak;
+            dog.name = "max";
+            print speak();
+            print speak;
+
+            fun shout() { return "hey"; }
+            dog.shout = shout;
+            print dog.shout();
+            print dog.init("fido") == dog;
+        "#);
+
+        assert!(result.is_ok());
+        assert_eq!(
+            output,
+            concat!(
+                "\"rex makes a sound, woof\"\n",
+                "Dog instance\n",
+                "Dog\n",
+                "\"max makes a sound, woof\"\n",
+                "<fn speak>\n",
+                "\"hey\"\n",
+                "true\n",
+            )
+        );
+    }
+
+    #[test]
+    fn reports_class_errors() {
+        let error = |source| run(source).1.unwrap_err().error.to_string();
+
+        assert_eq!(
+            error("var a = 1; print a.b;"),
+            "only instances have properties"
+        );
+        assert_eq!(error("class A {} A().b();"), "undefined property b");
+        assert_eq!(error("class A {} A(1);"), "expected 0 arguments but got 1");
+        assert_eq!(
+            error("var A = 1; class B < A {}"),
+            "superclass must be a class"
+        );
+    }
+
+    #[test]
+    fn caches_method_lookups() {
+        let output = Output::default();
+        let mut vm = Vm::with_output(output.clone());
+        let (output, result) = run_vm(
+            &mut vm,
+            &output,
+            compile(
+                r#"
+            class A { name() { return "a"; } }
+            class B < A {}
+            class C { name() { return "c"; } }
+
+            var a = A();
+            var s = "";
+            for (var i = 0; i < 10; i = i + 1) {
+                s = s + a.name();
+            }
+            print s;
+
+            // Fields shadow cached methods
+            a.name = C().name;
+            print a.name();
+
+            var objects = A();
+            for (var i = 0; i < 4; i = i + 1) {
+                if (i == 2) objects = B(); else if (i == 3) objects = C();
+                var method = objects.name;
+                print method();
+            }
+        "#,
+            ),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            output,
+            "\"aaaaaaaaaa\"\n\"c\"\n\"a\"\n\"a\"\n\"a\"\n\"c\"\n"
+        );
+        // The monomorphic invoke misses once, the get property misses whenever the class changes
+        // and `C().name` misses the first time it is seen
+        assert_eq!(
+            vm.cache_stats(),
+            CacheStats {
+                hits: 10,
+                misses: 5
+            }
+        );
+    }
+
+    #[test]
+    fn allocates_a_cache_for_each_site() {
+        let script = compile(
+            r#"
+            var a = nil;
+            print a.b;
+            a.c(1, 2);
+            a.d = 3;
+        "#,
+        );
+        let sites: Vec<_> = script
+            .chunk
+            .instructions()
+            .filter(|(_, op)| matches!(op, OpCode::GetProperty(_) | OpCode::Invoke(..)))
+            .map(|(offset, _)| offset)
+            .collect();
+
+        let mut vm = Vm::new();
+        let function = vm.load_function(Rc::new(script));
+        let Object::Function(function) = vm.heap.get(function) else {
+            panic!("expected a function");
+        };
+        let offsets: Vec<_> = function.caches.iter().map(|site| site.offset).collect();
+
+        assert_eq!(offsets.len(), 2);
+        assert_eq!(offsets, sites);
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn traces_execution() {
+        let output = Output::default();
+        let mut vm = Vm::with_output(output.clone());
+        vm.set_trace_execution(true);
+
+        let (output, result) = run_vm(&mut vm, &output, compile("print -1;"));
+
+        assert!(result.is_ok());
+        assert_eq!(
+            output,
+            concat!(
+                "          [ <script> ]\n",
+                "0000    1 OP_CONSTANT        0 '1'\n",
+                "          [ <script> ][ 1 ]\n",
+                "0002    | OP_NEGATE\n",
+                "          [ <script> ][ -1 ]\n",
+                "0003    | OP_PRINT\n",
+                "-1\n",
+                "          [ <script> ]\n",
+                "0004    | OP_NIL\n",
+                "          [ <script> ][ nil ]\n",
+                "0005    | OP_RETURN\n",
+            )
+        );
+    }
+
+    #[test]
+    fn collects_unreachable_objects() {
+        let output = Output::default();
+        let mut vm = Vm::with_output(output.clone());
+        vm.set_gc_stress(true);
+
+        let (output, result) = run_vm(
+            &mut vm,
+            &output,
+            compile(
+                r#"
+            var s = "";
+            for (var i = 0; i < 100; i = i + 1) {
+                s = s + "a";
+            }
+            print s == "a" + s;
+        "#,
+            ),
+        );
+        assert!(result.is_ok());
+        assert_eq!(output, "false\n");
+
+        // Only the names of the globals, the clock native, the name of initializers and the final
+        // string held in s survive
+        vm.collect_garbage();
+        assert_eq!(vm.heap.live_objects(), 5);
+    }
+
+    #[test]
+    fn grows_heap_threshold() {
+        let output = Output::default();
+        let mut vm = Vm::with_output(output.clone());
+
+        let (_, result) = run_vm(
+            &mut vm,
+            &output,
+            compile(
+                r#"
+            var s = "";
+            for (var i = 0; i < 2000; i = i + 1) {
+                s = s + "abcdefghijklmnopqrstuvwxyz";
+            }
+        "#,
+            ),
+        );
+        assert!(result.is_ok());
+
+        // Without collections the intermediate strings would take up around 50MB
+        assert!(vm.heap.bytes_allocated() < 4 * 1024 * 1024);
+        assert!(vm.heap.bytes_allocated() >= 2000 * 26);
+    }
+
+    #[test]
+    fn reports_runtime_errors() {
+        let error = |source| run(source).1.unwrap_err().error;
+
+        assert!(matches!(
+            error("print 1 / 0;"),
+            RuntimeError::DivisionByZero
+        ));
+        assert!(matches!(
+            error("print 1 + nil;"),
+            RuntimeError::TypeError { .. }
+        ));
+        assert!(matches!(
+            error("print undefined;"),
+            RuntimeError::Undefined { .. }
+        ));
+        assert!(matches!(
+            error("fun f(a) {} f();"),
+            RuntimeError::TypeError { .. }
+        ));
+
+        let mut script = Function::new("", 0);
+        script.chunk.push_op(OpCode::Pop, 1);
+        let mut vm = Vm::with_output(Output::default());
+        assert!(matches!(
+            vm.interpret(script).unwrap_err().error,
+            RuntimeError::InvalidBytecode(_)
+        ));
+    }
+
+    #[test]
+    fn rejects_operands_of_the_wrong_kind() {
+        use crate::chunk::Constant as PoolConstant;
+        use OpCode::*;
+
+        let error = |ops: &[OpCode]| {
+            let mut method = Function::new("m", 0);
+            method.chunk.push_op(Nil, 1);
+            method.chunk.push_op(Return, 1);
+
+            let mut script = Function::new("", 0);
+            script.chunk.push_constant(PoolConstant::String("A".into()));
+            script
+                .chunk
+                .push_constant(PoolConstant::Function(Rc::new(method)));
+            for &op in ops {
+                script.chunk.push_op(op, 1);
+            }
+            let mut vm = Vm::with_output(Output::default());
+            match vm.interpret(script).unwrap_err().error {
+                RuntimeError::InvalidBytecode(e) => (e.offset, e.kind),
+                e => panic!("expected invalid bytecode found {}", e),
+            }
+        };
+        let class = VerifyErrorKind::WrongOperandType { expected: "class" };
+
+        // Each chunk passes verification as only the depth of the stack is checked
+        assert_eq!(
+            error(&[Nil, Nil, Method(0), Pop, Nil, Return]),
+            (
+                2,
+                VerifyErrorKind::WrongOperandType {
+                    expected: "closure"
+                }
+            )
+        );
+        assert_eq!(
+            error(&[Nil, Closure(1), Method(0), Pop, Nil, Return]),
+            (3, class.clone())
+        );
+        assert_eq!(
+            error(&[Class(0), Nil, Inherit, Pop, Nil, Return]),
+            (3, class.clone())
+        );
+        assert_eq!(
+            error(&[Nil, Nil, GetSuper(0), Pop, Nil, Return]),
+            (2, class.clone())
+        );
+        assert_eq!(
+            error(&[Nil, Nil, SuperInvoke(0, 0), Pop, Nil, Return]),
+            (2, class)
+        );
+    }
+
+    #[test]
+    fn traces_runtime_errors() {
+        let (_, result) = run(r#"
+            fun inner(a) {
+                return a /
+                    0;
+            }
+
+            fun outer() {
+                return inner(1);
+            }
+
+            outer();
+        "#);
+
+        let error = result.unwrap_err();
+        assert!(matches!(error.error, RuntimeError::DivisionByZero));
+        assert_eq!(
+            error.to_string(),
+            "division by zero\n[line 3] in inner()\n[line 8] in outer()\n[line 11] in script"
+        );
+    }
+
+    #[test]
+    fn reports_stack_overflow() {
+        let (output, result) = run(r#"
+            fun count(n) {
+                if (n == 0) return 0;
+                return count(n - 1) + 1;
+            }
+
+            print count(999);
+            print count(1000);
+        "#);
+
+        assert_eq!(output, "999\n");
+        let error = result.unwrap_err();
+        assert!(matches!(error.error, RuntimeError::StackOverflow));
+        assert_eq!(error.trace.len(), STACK_OVERFLOW_TRACE_LEN);
+        assert_eq!(error.trace[0].to_string(), "[line 4] in count()");
+
+        let out = Output::default();
+        let mut vm = Vm::with_output(out.clone());
+        vm.set_max_call_depth(2);
+        let source = "fun f() { return 1; } fun g() { return f(); } print g(); print f;";
+        let (output, result) = run_vm(&mut vm, &out, compile(source));
+        assert!(result.is_ok());
+        assert_eq!(output, "1\n<fn f>\n");
+
+        let source = "fun f() { return 1; } fun g() { return f(); } fun h() { return g(); } h();";
+        let (_, result) = run_vm(&mut vm, &out, compile(source));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "stack overflow\n[line 1] in g()\n[line 1] in h()\n[line 1] in script"
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::object::Native;
+use crate::value::Value;
+use crate::vm::RResult;
+
+pub(super) const NATIVES: &[Native] = &[Native {
+    name: "clock",
+    arity: 0,
+    function: clock,
+}];
+
+fn clock(_args: &[Value]) -> RResult<Value> {
+    Ok(Value::number(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64(),
+    ))
+}
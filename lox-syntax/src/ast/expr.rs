@@ -5,6 +5,7 @@ use itertools::Itertools;
 use crate::span::Span;
 use crate::token::{Token, TokenKind};
 
+use super::stmt::FunDecl;
 use super::Identifier;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -59,6 +60,10 @@ pub enum Expr {
     Set(Set),
     This(This),
     Super(Super),
+    Lambda(Lambda),
+    List(List),
+    Index(Index),
+    SetIndex(SetIndex),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -152,6 +157,38 @@ pub struct Super {
     pub method: Identifier,
 }
 
+/// An anonymous function, `fun (a, b) { ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lambda {
+    pub span: Span,
+    /// The function, which is named `lambda` as it has no name of its own.
+    pub decl: FunDecl,
+}
+
+/// A list literal, `[a, b, c]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct List {
+    pub span: Span,
+    pub items: Vec<Expr>,
+}
+
+/// Reads an element of a list, `xs[i]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub span: Span,
+    pub object: Box<Expr>,
+    pub index: Box<Expr>,
+}
+
+/// Assigns to an element of a list, `xs[i] = v`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetIndex {
+    pub span: Span,
+    pub object: Box<Expr>,
+    pub index: Box<Expr>,
+    pub value: Box<Expr>,
+}
+
 impl Expr {
     pub fn span(&self) -> Span {
         use Expr::*;
@@ -169,10 +206,21 @@ impl Expr {
             Set(s) => s.span,
             This(t) => t.span,
             Super(s) => s.span,
+            Lambda(l) => l.span,
+            List(l) => l.span,
+            Index(i) => i.span,
+            SetIndex(s) => s.span,
         }
     }
 }
 
+impl Value {
+    /// `nil` and `false` are falsy, every other value is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+}
+
 impl Literal {
     pub fn new(span: Span, value: Value) -> Self {
         Self { value, span }
@@ -311,6 +359,44 @@ impl Super {
     }
 }
 
+impl Lambda {
+    pub fn new(span: Span, decl: FunDecl) -> Self {
+        Self { span, decl }
+    }
+}
+
+impl List {
+    pub fn new(span: Span, items: Vec<Expr>) -> Self {
+        Self { span, items }
+    }
+}
+
+impl Index {
+    pub fn new(span: Span, object: impl Into<Box<Expr>>, index: impl Into<Box<Expr>>) -> Self {
+        Self {
+            span,
+            object: object.into(),
+            index: index.into(),
+        }
+    }
+}
+
+impl SetIndex {
+    pub fn new(
+        span: Span,
+        object: impl Into<Box<Expr>>,
+        index: impl Into<Box<Expr>>,
+        value: impl Into<Box<Expr>>,
+    ) -> Self {
+        Self {
+            span,
+            object: object.into(),
+            index: index.into(),
+            value: value.into(),
+        }
+    }
+}
+
 // impl Display
 
 impl Display for UnOp {
@@ -436,6 +522,38 @@ impl Display for Super {
     }
 }
 
+impl Display for Lambda {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "(fun ({}))",
+            self.decl.params.iter().map(|param| &param.name).join(", ")
+        )
+    }
+}
+
+impl Display for List {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}]", self.items.iter().join(", "))
+    }
+}
+
+impl Display for Index {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(index {} {})", self.object, self.index)
+    }
+}
+
+impl Display for SetIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "(set-index {} {} {})",
+            self.object, self.index, self.value
+        )
+    }
+}
+
 impl Display for Expr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use Expr::*;
@@ -452,6 +570,10 @@ impl Display for Expr {
             Set(s) => write!(f, "{}", s),
             This(t) => write!(f, "{}", t),
             Super(s) => write!(f, "{}", s),
+            Lambda(l) => write!(f, "{}", l),
+            List(l) => write!(f, "{}", l),
+            Index(i) => write!(f, "{}", i),
+            SetIndex(s) => write!(f, "{}", s),
         }
     }
 }
@@ -11,6 +11,8 @@ pub enum Stmt {
     Block(Block),
     If(If),
     While(While),
+    Break(Break),
+    Continue(Continue),
     FunDecl(FunDecl),
     Return(Return),
     ClassDecl(ClassDecl),
@@ -54,6 +56,19 @@ pub struct While {
     pub span: Span,
     pub cond: Expr,
     pub stmt: Box<Stmt>,
+    /// The increment clause of a desugared `for` loop, evaluated after each iteration including
+    /// those ended by `continue`.
+    pub increment: Option<Expr>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Break {
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Continue {
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -87,6 +102,8 @@ impl Stmt {
             Stmt::Block(b) => b.span,
             Stmt::If(i) => i.span,
             Stmt::While(w) => w.span,
+            Stmt::Break(b) => b.span,
+            Stmt::Continue(c) => c.span,
             Stmt::FunDecl(f) => f.span,
             Stmt::Return(r) => r.span,
             Stmt::ClassDecl(c) => c.span,
@@ -135,15 +152,33 @@ impl If {
 }
 
 impl While {
-    pub fn new(span: Span, cond: Expr, stmt: impl Into<Box<Stmt>>) -> Self {
+    pub fn new(
+        span: Span,
+        cond: Expr,
+        stmt: impl Into<Box<Stmt>>,
+        increment: Option<Expr>,
+    ) -> Self {
         Self {
             span,
             cond,
             stmt: stmt.into(),
+            increment,
         }
     }
 }
 
+impl Break {
+    pub fn new(span: Span) -> Self {
+        Self { span }
+    }
+}
+
+impl Continue {
+    pub fn new(span: Span) -> Self {
+        Self { span }
+    }
+}
+
 impl FunDecl {
     pub fn new(span: Span, id: Identifier, params: Vec<Identifier>, body: Vec<Stmt>) -> Self {
         Self {
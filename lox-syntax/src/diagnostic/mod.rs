@@ -0,0 +1,105 @@
+//! Errors reported to the user by any stage of the interpreters, independent of the stage which
+//! raised them so that they can all be rendered the same way.
+
+use std::fmt::{Display, Formatter};
+
+use crate::span::Span;
+
+pub use render::Renderer;
+
+mod render;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A message about the program along with the spans of the source it refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A stable identifier for the kind of diagnostic. Codes are grouped by the stage which
+    /// reports them: `E00xx` for parsing, `E01xx` for resolution, `E02xx` for compilation and
+    /// `E03xx` for runtime errors.
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// The source which caused the diagnostic, `None` if it can't be attributed to any source.
+    pub span: Option<Span>,
+    /// The 1-based line of the diagnostic for stages which don't track spans, ignored if `span`
+    /// is set.
+    pub line: Option<usize>,
+    /// Other source related to the diagnostic, each with a message explaining how.
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    /// A suggestion for how to fix the problem.
+    pub help: Option<String>,
+}
+
+/// A span of source with a message explaining its part in a diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            span: None,
+            line: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
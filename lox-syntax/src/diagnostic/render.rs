@@ -0,0 +1,308 @@
+use std::fmt::Write;
+
+use super::{Diagnostic, Severity};
+use crate::source_map::SourceFile;
+use crate::span::Span;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Renders diagnostics for a source file, printing each line they refer to with the spans
+/// underlined:
+///
+/// ```text
+/// error: division by zero
+///  --> script.lox:2:12
+///   |
+/// 2 |     return a / 0;
+///   |            ^^^^^
+/// ```
+pub struct Renderer<'a> {
+    file: &'a SourceFile,
+    colour: bool,
+}
+
+/// An underlined span on a single line of the source.
+struct Annotation<'a> {
+    line: usize,
+    /// 1-based column of the first underlined character.
+    column: usize,
+    width: usize,
+    primary: bool,
+    message: Option<&'a str>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(file: &'a SourceFile) -> Self {
+        Self {
+            file,
+            colour: false,
+        }
+    }
+
+    /// Highlights the output with ANSI escape codes.
+    pub fn with_colour(mut self, colour: bool) -> Self {
+        self.colour = colour;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut out = String::new();
+        let severity_style = match diagnostic.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+
+        let severity = match diagnostic.code {
+            Some(code) => format!("{}[{}]", diagnostic.severity, code),
+            None => diagnostic.severity.to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{}{}",
+            self.paint(severity_style, &severity),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        );
+
+        let mut annotations: Vec<_> = diagnostic
+            .span
+            .map(|span| self.annotation(span, true, None))
+            .into_iter()
+            .chain(
+                diagnostic
+                    .labels
+                    .iter()
+                    .map(|label| self.annotation(label.span, false, Some(&label.message))),
+            )
+            .collect();
+        annotations.sort_by_key(|annotation| (annotation.line, !annotation.primary));
+
+        let mut shows_line = false;
+        let mut gutter = annotations
+            .iter()
+            .map(|annotation| annotation.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let bar = self.paint(BLUE, "|");
+
+        let located = diagnostic
+            .span
+            .or_else(|| diagnostic.labels.first().map(|label| label.span));
+        if let Some(span) = located {
+            let _ = writeln!(
+                out,
+                "{:gutter$}{} {}",
+                "",
+                self.paint(BLUE, "-->"),
+                self.file.location(span),
+                gutter = gutter
+            );
+            let _ = writeln!(out, "{:gutter$} {}", "", bar, gutter = gutter);
+        } else if let Some(line) = diagnostic.line {
+            // Without a span the line is shown, if it's in the source, but nothing is underlined
+            gutter = line.to_string().len();
+            let _ = writeln!(
+                out,
+                "{:gutter$}{} {}:{}",
+                "",
+                self.paint(BLUE, "-->"),
+                self.file.name(),
+                line,
+                gutter = gutter
+            );
+            if line <= self.file.line_count() {
+                let _ = writeln!(out, "{:gutter$} {}", "", bar, gutter = gutter);
+                let number = self.paint(BLUE, &line.to_string());
+                let _ = writeln!(out, "{} {} {}", number, bar, self.file.line(line));
+                shows_line = true;
+            }
+        }
+
+        let mut previous = None;
+        for annotation in annotations.iter() {
+            if previous != Some(annotation.line) {
+                if matches!(previous, Some(line) if line + 1 < annotation.line) {
+                    let _ = writeln!(out, "{}", self.paint(BLUE, "..."));
+                }
+
+                let number = self.paint(BLUE, &format!("{:>gutter$}", annotation.line));
+                let _ = writeln!(
+                    out,
+                    "{} {} {}",
+                    number,
+                    bar,
+                    self.file.line(annotation.line)
+                );
+                previous = Some(annotation.line);
+            }
+
+            let (style, marker) = match annotation.primary {
+                true => (severity_style, "^"),
+                false => (BLUE, "-"),
+            };
+            let mut underline = marker.repeat(annotation.width);
+            if let Some(message) = annotation.message {
+                underline = format!("{} {}", underline, message);
+            }
+
+            // Format widths can't exceed `u16::MAX` so the indent is built up instead, lines may
+            // be longer than that
+            let _ = writeln!(
+                out,
+                "{:gutter$} {} {}{}",
+                "",
+                bar,
+                " ".repeat(annotation.column - 1),
+                self.paint(style, &underline),
+                gutter = gutter
+            );
+        }
+
+        let has_source = shows_line || !annotations.is_empty();
+        if has_source && (!diagnostic.notes.is_empty() || diagnostic.help.is_some()) {
+            let _ = writeln!(out, "{:gutter$} {}", "", bar, gutter = gutter);
+        }
+
+        let notes = diagnostic.notes.iter().map(|note| ("note", note));
+        for (kind, text) in notes.chain(diagnostic.help.iter().map(|help| ("help", help))) {
+            let _ = writeln!(
+                out,
+                "{:gutter$} {} {}: {}",
+                "",
+                self.paint(BLUE, "="),
+                self.paint(BOLD, kind),
+                text,
+                gutter = gutter
+            );
+        }
+
+        out
+    }
+
+    /// Underlines `span` on the line it starts on, spans which continue onto later lines are
+    /// underlined to the end of their first line.
+    fn annotation(&self, span: Span, primary: bool, message: Option<&'a str>) -> Annotation<'a> {
+        let range = span.range();
+        let (line, column) = self.file.line_column(range.start);
+        let (end_line, end_column) = self.file.line_column(range.end);
+
+        let end = match end_line == line {
+            true => end_column,
+            false => self.file.line(line).chars().count() + 1,
+        };
+
+        Annotation {
+            line,
+            column,
+            width: end.saturating_sub(column).max(1),
+            primary,
+            message,
+        }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        match self.colour {
+            true => format!("{}{}{}", style, text, RESET),
+            false => text.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, diagnostic: &Diagnostic) -> String {
+        let file = SourceFile::new("test.lox", source);
+        Renderer::new(&file).render(diagnostic)
+    }
+
+    #[test]
+    fn underlines_spans() {
+        let source = "fun f(a) {\n    return a /\n        0;\n}\n\nprint f(1);\n";
+        let diagnostic = Diagnostic::error("division by zero")
+            .with_span(Span::new(22, 35))
+            .with_label(Span::new(46, 50), "call to f()")
+            .with_help("check the divisor");
+
+        assert_eq!(
+            render(source, &diagnostic),
+            concat!(
+                "error: division by zero\n",
+                " --> test.lox:2:12\n",
+                "  |\n",
+                "2 |     return a /\n",
+                "  |            ^^^\n",
+                "...\n",
+                "6 | print f(1);\n",
+                "  |       ---- call to f()\n",
+                "  |\n",
+                "  = help: check the divisor\n",
+            )
+        );
+    }
+
+    #[test]
+    fn underlines_spans_far_along_a_line() {
+        let column = u16::MAX as usize + 10;
+        let source = format!("{}x", " ".repeat(column - 1));
+        let diagnostic = Diagnostic::error("unexpected x").with_span(Span::new(column - 1, column));
+
+        let rendered = render(&source, &diagnostic);
+        assert!(rendered.contains(&format!(" --> test.lox:1:{}\n", column)));
+        assert!(rendered.ends_with(&format!("  | {}^\n", " ".repeat(column - 1))));
+    }
+
+    #[test]
+    fn renders_diagnostics_without_spans() {
+        let diagnostic = Diagnostic::new(Severity::Warning, "unused").with_note("a note");
+        assert_eq!(
+            render("", &diagnostic),
+            "warning: unused\n = note: a note\n"
+        );
+
+        let diagnostic = Diagnostic::error("expected ;").with_span(Span::new(7, 7));
+        assert_eq!(
+            render("print 1", &diagnostic),
+            "error: expected ;\n --> test.lox:1:8\n  |\n1 | print 1\n  |        ^\n"
+        );
+    }
+
+    #[test]
+    fn renders_codes_and_lines() {
+        let diagnostic = Diagnostic::error("division by zero")
+            .with_code("E0302")
+            .with_line(2)
+            .with_note("[line 2] in script");
+        assert_eq!(
+            render("print 1;\nprint 1 / 0;\n", &diagnostic),
+            concat!(
+                "error[E0302]: division by zero\n",
+                " --> test.lox:2\n",
+                "  |\n",
+                "2 | print 1 / 0;\n",
+                "  |\n",
+                "  = note: [line 2] in script\n",
+            )
+        );
+
+        // The line isn't shown if the source isn't available
+        assert_eq!(
+            render("", &diagnostic),
+            "error[E0302]: division by zero\n --> test.lox:2\n  = note: [line 2] in script\n"
+        );
+    }
+
+    #[test]
+    fn highlights_with_colour() {
+        let file = SourceFile::new("test.lox", "x;");
+        let diagnostic = Diagnostic::error("undefined variable x").with_span(Span::new(0, 1));
+        let rendered = Renderer::new(&file).with_colour(true).render(&diagnostic);
+
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: undefined variable x"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+}
@@ -2,6 +2,9 @@ pub use ast::Identifier;
 pub use parser::Parser;
 
 pub mod ast;
+pub mod diagnostic;
+pub mod optimize;
 pub mod parser;
+pub mod source_map;
 pub mod span;
 mod token;
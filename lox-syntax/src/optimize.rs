@@ -0,0 +1,283 @@
+//! Constant folding and dead branch elimination over the AST.
+//!
+//! Expressions whose operands are all literals are replaced by a literal holding their value and
+//! `if` and `while` statements whose condition is a literal are replaced by the branch which is
+//! taken. Folded expressions take the span of the expression they replace so that diagnostics
+//! still point at the original source.
+//!
+//! Only operations which can't fail and whose result is the same in both interpreters are
+//! folded, anything else, such as `1 / 0`, is left for the interpreter to report. `!` is never
+//! folded as the tree-walk interpreter doesn't negate its operand.
+
+use crate::ast::expr::{
+    Assign, BinOp, Binary, Call, Expr, Get, Grouping, Index, Lambda, List, Literal, Logical,
+    LogicalOp, Set, SetIndex, UnOp, Unary, Value,
+};
+use crate::ast::stmt::{Block, ClassDecl, ExprStmt, FunDecl, If, Print, Return, Stmt, Var, While};
+use crate::span::Span;
+
+/// Folds the constant expressions in `stmts` and removes branches which can never be taken.
+///
+/// Statements in removed branches are never seen by later passes so the AST must be checked for
+/// errors, by the tree-walk resolver or `Compiler::check`, before it is optimized.
+pub fn optimize(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts.into_iter().filter_map(optimize_stmt).collect()
+}
+
+/// Optimizes `stmt`, returning `None` if it has no effect.
+fn optimize_stmt(stmt: Stmt) -> Option<Stmt> {
+    let stmt = match stmt {
+        Stmt::Print(p) => Stmt::Print(Print::new(p.span, fold(p.expr))),
+        Stmt::Expr(e) => Stmt::Expr(ExprStmt::new(e.span, fold(e.expr))),
+        Stmt::Var(v) => Stmt::Var(Var::new(v.span, v.id, fold(v.expr))),
+        Stmt::Block(b) => Stmt::Block(Block::new(b.span, optimize(b.stmts))),
+        Stmt::If(i) => {
+            let cond = fold(i.cond);
+            if let Expr::Literal(literal) = &cond {
+                let branch = if literal.value.is_truthy() {
+                    Some(i.then_stmt)
+                } else {
+                    i.else_stmt
+                };
+                return branch.and_then(|branch| optimize_stmt(*branch));
+            }
+
+            Stmt::If(If::new(
+                i.span,
+                cond,
+                optimize_branch(*i.then_stmt),
+                i.else_stmt.map(|stmt| optimize_branch(*stmt)),
+            ))
+        }
+        Stmt::While(w) => {
+            let cond = fold(w.cond);
+            if matches!(&cond, Expr::Literal(literal) if !literal.value.is_truthy()) {
+                return None;
+            }
+
+            Stmt::While(While::new(
+                w.span,
+                cond,
+                optimize_branch(*w.stmt),
+                w.increment.map(fold),
+            ))
+        }
+        Stmt::Break(_) | Stmt::Continue(_) => stmt,
+        Stmt::FunDecl(f) => Stmt::FunDecl(optimize_fun_decl(f)),
+        Stmt::Return(r) => Stmt::Return(Return::new(r.span, fold(r.expr))),
+        Stmt::ClassDecl(c) => Stmt::ClassDecl(ClassDecl::new(
+            c.span,
+            c.id,
+            c.super_class,
+            c.methods.into_iter().map(optimize_fun_decl).collect(),
+        )),
+    };
+
+    Some(stmt)
+}
+
+/// Optimizes the body of an `if` or `while`, a body with no effect is replaced by an empty block.
+fn optimize_branch(stmt: Stmt) -> Stmt {
+    let span = stmt.span();
+    optimize_stmt(stmt).unwrap_or_else(|| Stmt::Block(Block::new(span, Vec::new())))
+}
+
+fn optimize_fun_decl(fun_decl: FunDecl) -> FunDecl {
+    FunDecl::new(
+        fun_decl.span,
+        fun_decl.id,
+        fun_decl.params,
+        optimize(fun_decl.body),
+    )
+}
+
+/// Folds the constant subexpressions of `expr`.
+pub fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::Unary(u) => {
+            let operand = fold(*u.expr);
+            match (u.op, &operand) {
+                (
+                    UnOp::Minus,
+                    Expr::Literal(Literal {
+                        value: Value::Number(n),
+                        ..
+                    }),
+                ) => literal(u.span, Value::Number(-n)),
+                _ => Expr::Unary(Unary::new(u.span, u.op, operand)),
+            }
+        }
+        Expr::Binary(b) => {
+            let lhs = fold(*b.lhs);
+            let rhs = fold(*b.rhs);
+            if let (Expr::Literal(l), Expr::Literal(r)) = (&lhs, &rhs) {
+                if let Some(value) = fold_binary(b.op, &l.value, &r.value) {
+                    return literal(b.span, value);
+                }
+            }
+
+            Expr::Binary(Binary::new(b.span, b.op, lhs, rhs))
+        }
+        Expr::Logical(l) => {
+            let lhs = fold(*l.lhs);
+            let rhs = fold(*l.rhs);
+            match &lhs {
+                Expr::Literal(literal) => {
+                    // The right hand side is only evaluated if the left doesn't decide the result
+                    match (literal.value.is_truthy(), l.op) {
+                        (true, LogicalOp::Or) | (false, LogicalOp::And) => with_span(lhs, l.span),
+                        _ => rhs,
+                    }
+                }
+                _ => Expr::Logical(Logical::new(l.span, l.op, lhs, rhs)),
+            }
+        }
+        Expr::Grouping(g) => match fold(*g.expr) {
+            literal @ Expr::Literal(_) => with_span(literal, g.span),
+            expr => Expr::Grouping(Grouping::new(g.span, expr)),
+        },
+        Expr::Assign(a) => Expr::Assign(Assign::new(a.span, a.var, fold(*a.expr))),
+        Expr::Call(c) => Expr::Call(Call::new(
+            c.span,
+            fold(*c.callee),
+            c.args.into_iter().map(fold).collect(),
+        )),
+        Expr::Get(g) => Expr::Get(Get::new(g.span, fold(*g.object), g.property)),
+        Expr::Set(s) => Expr::Set(Set::new(
+            s.span,
+            fold(*s.object),
+            s.property,
+            fold(*s.value),
+        )),
+        Expr::Lambda(l) => Expr::Lambda(Lambda::new(l.span, optimize_fun_decl(l.decl))),
+        Expr::List(l) => Expr::List(List::new(l.span, l.items.into_iter().map(fold).collect())),
+        Expr::Index(i) => Expr::Index(Index::new(i.span, fold(*i.object), fold(*i.index))),
+        Expr::SetIndex(s) => Expr::SetIndex(SetIndex::new(
+            s.span,
+            fold(*s.object),
+            fold(*s.index),
+            fold(*s.value),
+        )),
+        expr @ (Expr::Literal(_) | Expr::Var(_) | Expr::This(_) | Expr::Super(_)) => expr,
+    }
+}
+
+/// Evaluates `l op r`, returning `None` if the operation fails at runtime.
+fn fold_binary(op: BinOp, l: &Value, r: &Value) -> Option<Value> {
+    use BinOp::*;
+
+    let value = match (l, r) {
+        (Value::Number(l), Value::Number(r)) => match op {
+            Divide if *r == 0f64 => return None,
+            Divide => Value::Number(l / r),
+            Multiply => Value::Number(l * r),
+            Add => Value::Number(l + r),
+            Subtract => Value::Number(l - r),
+            Equal => Value::Boolean(l == r),
+            NotEqual => Value::Boolean(l != r),
+            Greater => Value::Boolean(l > r),
+            GreaterEqual => Value::Boolean(l >= r),
+            Less => Value::Boolean(l < r),
+            LessEqual => Value::Boolean(l <= r),
+        },
+        (Value::String(l), Value::String(r)) if op == Add => Value::String(format!("{}{}", l, r)),
+        (l, r) if op == Equal => Value::Boolean(l == r),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+fn literal(span: Span, value: Value) -> Expr {
+    Expr::Literal(Literal::new(span, value))
+}
+
+fn with_span(expr: Expr, span: Span) -> Expr {
+    match expr {
+        Expr::Literal(literal) => Expr::Literal(Literal { span, ..literal }),
+        expr => expr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parser;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let mut parser = Parser::new(source);
+        let stmts = parser.parse();
+        assert!(parser.diagnostics().is_empty());
+        stmts
+    }
+
+    fn folded(source: &str) -> Expr {
+        match optimize(parse(&format!("print {};", source))).as_slice() {
+            [Stmt::Print(print)] => print.expr.clone(),
+            stmts => panic!("expected print statement found {:?}", stmts),
+        }
+    }
+
+    #[test]
+    fn folds_literal_expressions() {
+        let source = "(1 + 2) * -3";
+        assert_eq!(
+            folded(source),
+            literal(Span::new(6, 18), Value::Number(-9.0))
+        );
+
+        assert_eq!(
+            folded(r#""con" + "cat" == "concat""#),
+            literal(Span::new(6, 31), Value::Boolean(true))
+        );
+        assert_eq!(
+            folded("nil or 2 < 1"),
+            literal(Span::new(13, 18), Value::Boolean(false))
+        );
+        assert_eq!(
+            folded("false and x"),
+            literal(Span::new(6, 17), Value::Boolean(false))
+        );
+        assert!(matches!(folded("true and x"), Expr::Var(_)));
+    }
+
+    #[test]
+    fn leaves_failing_operations() {
+        assert!(matches!(folded("1 / 0"), Expr::Binary(_)));
+        assert!(matches!(folded("1 + nil"), Expr::Binary(_)));
+        assert!(matches!(folded("-\"a\""), Expr::Unary(_)));
+        assert!(matches!(folded("!true"), Expr::Unary(_)));
+        assert!(matches!(folded("\"a\" != \"b\""), Expr::Binary(_)));
+
+        // The operands of an expression which can't be folded are still folded
+        match folded("x + 1 * 2") {
+            Expr::Binary(b) => assert_eq!(*b.rhs, literal(Span::new(10, 15), Value::Number(2.0))),
+            expr => panic!("expected binary expression found {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn removes_dead_branches() {
+        let stmts = optimize(parse(
+            r#"
+            if (1 > 2) print "then"; else print "else";
+            if (nil) print "dead";
+            while (false) print "dead";
+            while (x) if (false) print "dead";
+        "#,
+        ));
+
+        assert!(matches!(
+            stmts.as_slice(),
+            [Stmt::Print(_), Stmt::While(While { stmt, .. })]
+                if matches!(stmt.as_ref(), Stmt::Block(Block { stmts, .. }) if stmts.is_empty())
+        ));
+        match &stmts[0] {
+            Stmt::Print(print) => assert_eq!(
+                print.expr,
+                literal(Span::new(49, 55), Value::String("else".into()))
+            ),
+            stmt => panic!("expected print statement found {:?}", stmt),
+        }
+    }
+}
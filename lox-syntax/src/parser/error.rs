@@ -1,6 +1,8 @@
 use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
 
+use crate::diagnostic::Diagnostic;
+use crate::parser::MAX_NESTING_DEPTH;
 pub use crate::token::ScanError;
 use crate::{span::Span, token::TokenKind};
 
@@ -21,14 +23,41 @@ pub enum ParseError {
         span: Span,
         message: Cow<'static, str>,
     },
+    /// The statement or expression starting at `span` would exceed the parser's maximum nesting
+    /// depth.
+    TooDeeplyNested {
+        span: Span,
+    },
 }
 
 impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::ScanError { span, .. }
+            | ParseError::UnexpectedToken { span, .. }
+            | ParseError::InvalidAssignment { span, .. }
+            | ParseError::TooDeeplyNested { span } => *span,
+        }
+    }
+
+    /// The stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::ScanError { error, .. } => match error {
+                ScanError::UnrecognizedToken { .. } => "E0001",
+                ScanError::UnterminatedString => "E0002",
+            },
+            ParseError::UnexpectedToken { .. } => "E0003",
+            ParseError::InvalidAssignment { .. } => "E0004",
+            ParseError::TooDeeplyNested { .. } => "E0005",
+        }
+    }
+
     pub fn allows_continuation(&self) -> bool {
         match self {
             ParseError::ScanError { error, .. } => matches!(error, ScanError::UnterminatedString),
             ParseError::UnexpectedToken { kind, .. } => matches!(kind, &TokenKind::Eof),
-            ParseError::InvalidAssignment { .. } => false,
+            ParseError::InvalidAssignment { .. } | ParseError::TooDeeplyNested { .. } => false,
         }
     }
 }
@@ -40,6 +69,30 @@ impl Display for ParseError {
             ScanError { error, .. } => write!(f, "{}", error),
             UnexpectedToken { message, .. } => f.write_str(message),
             InvalidAssignment { message, .. } => f.write_str(message),
+            TooDeeplyNested { .. } => f.write_str("code is nested too deeply"),
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string())
+            .with_code(error.code())
+            .with_span(error.span());
+
+        match error {
+            ParseError::ScanError {
+                error: ScanError::UnterminatedString,
+                ..
+            } => diagnostic.with_help("add a closing `\"` to the end of the string"),
+            ParseError::InvalidAssignment { .. } => {
+                diagnostic.with_note("only variables, fields and list elements can be assigned to")
+            }
+            ParseError::TooDeeplyNested { .. } => diagnostic.with_note(format!(
+                "statements and expressions can be nested at most {} levels deep",
+                MAX_NESTING_DEPTH
+            )),
+            _ => diagnostic,
         }
     }
 }
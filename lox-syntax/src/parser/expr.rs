@@ -1,16 +1,18 @@
 use crate::ast::expr::{
-    Assign, Binary, Call, Expr, Get, Grouping, Literal, Logical, Set, Super, This, UnOp, Unary, Var,
+    Assign, Binary, Call, Expr, Get, Grouping, Index, Lambda, List, Literal, Logical, Set,
+    SetIndex, Super, This, UnOp, Unary, Var,
 };
 use crate::ast::util::{AssocOp, Fixity};
 use crate::ast::Identifier;
 use crate::parser::error::{PResult, ParseError};
+use crate::parser::stmt::FunctionType;
 use crate::parser::Parser;
 use crate::span::Span;
 use crate::token::TokenKind;
 
 impl<'a> Parser<'a> {
     pub(super) fn parse_expr(&mut self) -> PResult<Expr> {
-        self.parse_assoc_op_with_prec(0)
+        self.nested(|this| this.parse_assoc_op_with_prec(0))
     }
 
     // Parse 15 / 3 / 5
@@ -38,6 +40,7 @@ impl<'a> Parser<'a> {
                 break;
             }
 
+            self.deepen()?;
             self.bump();
 
             let fixity_adjustment = match op.fixity() {
@@ -45,7 +48,9 @@ impl<'a> Parser<'a> {
                 Fixity::Left => 1,
             };
 
-            let rhs = self.parse_assoc_op_with_prec(next_precedence + fixity_adjustment)?;
+            let rhs = self.nested(|this| {
+                this.parse_assoc_op_with_prec(next_precedence + fixity_adjustment)
+            })?;
 
             let span = lhs.span().union(&rhs.span());
 
@@ -67,6 +72,9 @@ impl<'a> Parser<'a> {
                         Expr::Assign(Assign::new(var.span.union(&rhs.span()), var.id, rhs))
                     }
                     Expr::Get(get) => Expr::Set(Set::new(span, get.object, get.property, rhs)),
+                    Expr::Index(index) => {
+                        Expr::SetIndex(SetIndex::new(span, index.object, index.index, rhs))
+                    }
                     _ => {
                         return Err(ParseError::InvalidAssignment {
                             span: lhs.span(),
@@ -124,8 +132,10 @@ impl<'a> Parser<'a> {
                     method,
                 )))
             }
+            T::Fun => self.parse_lambda(),
             T::Minus | T::Bang => self.parse_unary(),
             T::LeftParen => self.parse_grouping(),
+            T::LeftBracket => self.parse_list(),
             T::Error(error) => Err(ParseError::ScanError { error, span }),
             _ => Err(ParseError::UnexpectedToken {
                 span,
@@ -135,12 +145,17 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn parse_lambda(&mut self) -> PResult<Expr> {
+        let decl = self.parse_fun_inner(FunctionType::Lambda)?;
+        self.parse_call_or_get(Expr::Lambda(Lambda::new(decl.span, decl)))
+    }
+
     fn parse_unary(&mut self) -> PResult<Expr> {
         let op = self.bump();
         let op_span = op.span;
         let op = UnOp::from_token(op).unwrap();
 
-        let expr = self.parse_prefix()?;
+        let expr = self.nested(Self::parse_prefix)?;
 
         Ok(Expr::Unary(Unary::new(
             op_span.union(&expr.span()),
@@ -167,9 +182,33 @@ impl<'a> Parser<'a> {
         Ok(Expr::Grouping(Grouping::new(span, expr)))
     }
 
+    fn parse_list(&mut self) -> PResult<Expr> {
+        let left_bracket_span = self.bump().span;
+
+        let mut items = Vec::new();
+        if !self.peek().kind.match_kind(&TokenKind::RightBracket) {
+            items.push(self.parse_expr()?);
+            while self.matches(&[TokenKind::Comma]).is_some() {
+                items.push(self.parse_expr()?);
+            }
+        }
+        let right_bracket = self.expect(
+            TokenKind::RightBracket,
+            "expect ']' after list items".into(),
+        )?;
+
+        let span = left_bracket_span.union(&right_bracket.span);
+
+        self.parse_call_or_get(Expr::List(List::new(span, items)))
+    }
+
     fn parse_call_or_get(&mut self, mut expr: Expr) -> PResult<Expr> {
         use TokenKind as T;
         loop {
+            if matches!(self.peek().kind, T::LeftParen | T::Dot | T::LeftBracket) {
+                self.deepen()?;
+            }
+
             match self.peek().kind {
                 T::LeftParen => {
                     let (right_span, args) = self.parse_arguments()?;
@@ -180,6 +219,14 @@ impl<'a> Parser<'a> {
                     let property = self.expect_identifier()?;
                     expr = Expr::Get(Get::new(expr.span().union(&property.span), expr, property))
                 }
+                T::LeftBracket => {
+                    self.bump(); // consume left bracket
+                    let index = self.parse_expr()?;
+                    let right_bracket = self
+                        .expect(T::RightBracket, "expect ']' after index".into())?
+                        .span;
+                    expr = Expr::Index(Index::new(expr.span().union(&right_bracket), expr, index))
+                }
                 _ => {
                     break;
                 }
@@ -446,6 +493,53 @@ mod tests {
         assert_eq!(expected, expr);
     }
 
+    #[test]
+    fn parse_list() {
+        let source = "[1, [], a]";
+        let expected = Expr::List(List::new(
+            Span::new(0, 10),
+            vec![
+                Expr::Literal(Literal::new(Span::new(1, 2), Value::Number(1.0))),
+                Expr::List(List::new(Span::new(4, 6), Vec::new())),
+                Expr::Var(Var::new(
+                    Span::new(8, 9),
+                    Identifier::new(Span::new(8, 9), "a", 0),
+                )),
+            ],
+        ));
+
+        let mut parser = Parser::new(source);
+        let expr = parser.parse_expr().unwrap();
+
+        assert_eq!(expected, expr);
+    }
+
+    #[test]
+    fn parse_index() {
+        let source = "xs[0][i] = 1";
+        let expected = Expr::SetIndex(SetIndex::new(
+            Span::new(0, 12),
+            Expr::Index(Index::new(
+                Span::new(0, 5),
+                Expr::Var(Var::new(
+                    Span::new(0, 2),
+                    Identifier::new(Span::new(0, 2), "xs", 0),
+                )),
+                Expr::Literal(Literal::new(Span::new(3, 4), Value::Number(0.0))),
+            )),
+            Expr::Var(Var::new(
+                Span::new(6, 7),
+                Identifier::new(Span::new(6, 7), "i", 1),
+            )),
+            Expr::Literal(Literal::new(Span::new(11, 12), Value::Number(1.0))),
+        ));
+
+        let mut parser = Parser::new(source);
+        let expr = parser.parse_expr().unwrap();
+
+        assert_eq!(expected, expr);
+    }
+
     #[test]
     fn parse_super() {
         let source = "super.foo";
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::iter::Peekable;
 use std::mem;
 
 use error::{PResult, ParseError};
@@ -37,17 +38,23 @@ impl ParserState {
     }
 }
 
+/// The deepest that statements and expressions can be nested. Each pass over the AST recurses
+/// through it, so this limit is what keeps them within the native stack.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
 pub struct Parser<'a> {
     state: ParserState,
-    scanner: Scanner<'a>,
+    scanner: Peekable<Scanner<'a>>,
     current_token: Token,
     prev_token: Token,
     diagnostics: Vec<ParseError>,
+    /// The number of statements and expressions currently being parsed within each other.
+    depth: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(source: &'a str) -> Self {
-        let mut scanner = Scanner::new(source);
+        let mut scanner = Scanner::new(source).peekable();
         // Safe to unwrap as there should always be at least an EOF token
         let current_token = scanner.next().unwrap();
 
@@ -58,6 +65,7 @@ impl<'a> Parser<'a> {
             // Use EOF token as dummy to start the scanner
             prev_token: Token::new(TokenKind::Eof, Span::new(0, 0)),
             diagnostics: Vec::new(),
+            depth: 0,
         }
     }
 
@@ -84,7 +92,13 @@ impl<'a> Parser<'a> {
             match self.parse_declaration() {
                 Ok(stmt) => statements.push(stmt),
                 Err(e) => {
+                    // Recovering would report code which is nested too deeply again at each
+                    // level of nesting which remains
+                    let recoverable = !matches!(e, ParseError::TooDeeplyNested { .. });
                     self.diagnostics.push(e);
+                    if !recoverable {
+                        break;
+                    }
                     self.synchronize();
                 }
             }
@@ -115,7 +129,7 @@ impl<'a> Parser<'a> {
                 _ => {
                     if matches!(
                         self.peek().kind,
-                        Class | For | Fun | If | Print | Return | Var | While
+                        Break | Class | Continue | For | Fun | If | Print | Return | Var | While
                     ) {
                         break;
                     } else {
@@ -134,6 +148,13 @@ impl<'a> Parser<'a> {
         &self.prev_token
     }
 
+    /// Returns the token after the current one, needed to tell apart statements which start with
+    /// the same token.
+    fn peek_next(&mut self) -> &Token {
+        // The EOF token is the last token, it is the next token of itself
+        self.scanner.peek().unwrap_or(&self.current_token)
+    }
+
     fn is_at_end(&self) -> bool {
         self.current_token.kind == TokenKind::Eof
     }
@@ -186,6 +207,30 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses with `f` one level deeper in the nesting of statements and expressions, failing if
+    /// this would exceed `MAX_NESTING_DEPTH`. Any levels added by `deepen` within `f` are removed
+    /// once it returns.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> PResult<T>) -> PResult<T> {
+        let depth = self.depth;
+        self.deepen()?;
+        let result = f(self);
+        self.depth = depth;
+        result
+    }
+
+    /// Adds a level of nesting which lasts until the enclosing call to `nested` returns, used
+    /// when an expression is wrapped in another in a loop rather than through recursion.
+    fn deepen(&mut self) -> PResult<()> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(ParseError::TooDeeplyNested {
+                span: self.peek().span,
+            });
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
     fn increment(&mut self) -> usize {
         let next_id = self.state.variable_id + 1;
         std::mem::replace(&mut self.state.variable_id, next_id)
@@ -7,7 +7,9 @@ use crate::token::{ScanError, Token, TokenKind};
 
 static KEYWORDS: phf::Map<&'static str, TokenKind> = phf::phf_map! {
     "and" => TokenKind::And,
+    "break" => TokenKind::Break,
     "class" => TokenKind::Class,
+    "continue" => TokenKind::Continue,
     "else" => TokenKind::Else,
     "false" => TokenKind::False,
     "fun" => TokenKind::Fun,
@@ -49,6 +51,8 @@ impl ScannerInner<'_> {
                 ')' => token!(RightParen, start, 1),
                 '{' => token!(LeftBrace, start, 1),
                 '}' => token!(RightBrace, start, 1),
+                '[' => token!(LeftBracket, start, 1),
+                ']' => token!(RightBracket, start, 1),
                 ',' => token!(Comma, start, 1),
                 '.' => token!(Dot, start, 1),
                 '-' => token!(Minus, start, 1),
@@ -56,7 +60,7 @@ impl ScannerInner<'_> {
                 ';' => token!(Semicolon, start, 1),
                 '*' => token!(Star, start, 1),
                 '/' => {
-                    if self.peek().map_or(false, |c| c == '/') {
+                    if self.peek() == Some('/') {
                         self.take_until('\n');
                         self.scan_token()
                     } else {
@@ -72,7 +76,7 @@ impl ScannerInner<'_> {
                 '<' => self.take_select('=', token!(LessEqual, start, 2), token!(Less, start, 1)),
                 '=' => self.take_select('=', token!(EqualEqual, start, 2), token!(Equal, start, 1)),
                 '"' => self.string(),
-                c if c.is_digit(10) => self.number(),
+                c if c.is_ascii_digit() => self.number(),
                 c if c.is_alphabetic() || c == '_' => self.identifier(),
                 unrecognized => token!(
                     Error(ScanError::UnrecognizedToken { unrecognized }),
@@ -109,7 +113,7 @@ impl ScannerInner<'_> {
         match (self.peek(), self.peek_nth(1)) {
             (Some('.'), Some(n)) if n.is_numeric() => {
                 self.source_iter.next();
-                self.take_while(|c| c.is_digit(10))
+                self.take_while(|c| c.is_ascii_digit())
             }
             _ => {}
         }
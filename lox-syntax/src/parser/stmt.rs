@@ -1,4 +1,6 @@
-use crate::ast::stmt::{Block, ExprStmt, FunDecl, If, Print, Return, Stmt, Var, While};
+use crate::ast::stmt::{
+    Block, Break, Continue, ExprStmt, FunDecl, If, Print, Return, Stmt, Var, While,
+};
 use crate::ast::Identifier;
 use crate::ast::{
     expr::{Expr, Literal, Value},
@@ -11,9 +13,10 @@ use crate::token::{Token, TokenKind};
 static TERMINATOR: &str = "missing semicolon ';'";
 
 #[derive(Debug, Copy, Clone)]
-enum FunctionType {
+pub(super) enum FunctionType {
     Method,
     Function,
+    Lambda,
 }
 
 impl<'a> Parser<'a> {
@@ -24,7 +27,15 @@ impl<'a> Parser<'a> {
 
         match self.peek().kind {
             Var => self.parse_var_declaration(),
-            Fun => self.parse_fun_declaration(),
+            Fun => {
+                // Lambdas also start with `fun` but are followed by their parameters rather than
+                // a name
+                if matches!(self.peek_next().kind, LeftParen) {
+                    self.parse_stmt()
+                } else {
+                    self.parse_fun_declaration()
+                }
+            }
             Class => self.parse_class_declaration(),
             _ => self.parse_stmt(),
         }
@@ -55,7 +66,7 @@ impl<'a> Parser<'a> {
             .map(Stmt::FunDecl)
     }
 
-    fn parse_fun_inner(&mut self, function_type: FunctionType) -> PResult<FunDecl> {
+    pub(super) fn parse_fun_inner(&mut self, function_type: FunctionType) -> PResult<FunDecl> {
         let (start_span, id) = match function_type {
             FunctionType::Function => {
                 // consume fun keyword
@@ -65,6 +76,10 @@ impl<'a> Parser<'a> {
                 let id = self.expect_identifier()?;
                 (id.span, id)
             }
+            FunctionType::Lambda => {
+                let span = self.bump().span;
+                (span, Identifier::new(span, "lambda", self.increment()))
+            }
         };
         self.expect(
             TokenKind::LeftParen,
@@ -80,9 +95,12 @@ impl<'a> Parser<'a> {
         )?;
 
         let mut body: Vec<Stmt> = Vec::new();
-        while !self.peek().kind.match_kind(&TokenKind::RightBrace) && !self.is_at_end() {
-            body.push(self.parse_declaration()?);
-        }
+        self.nested(|this| {
+            while !this.peek().kind.match_kind(&TokenKind::RightBrace) && !this.is_at_end() {
+                body.push(this.parse_declaration()?);
+            }
+            Ok(())
+        })?;
         let end_span = self
             .expect(
                 TokenKind::RightBrace,
@@ -139,16 +157,17 @@ impl<'a> Parser<'a> {
     fn parse_stmt(&mut self) -> PResult<Stmt> {
         use TokenKind::*;
 
-        let token = self.peek();
-        match token.kind {
-            Print => self.parse_print(),
-            LeftBrace => self.parse_block(),
-            If => self.parse_if(),
-            While => self.parse_while(),
-            For => self.parse_for(),
-            Return => self.parse_return(),
-            _ => self.parse_expr_stmt(),
-        }
+        self.nested(|this| match this.peek().kind {
+            Print => this.parse_print(),
+            LeftBrace => this.parse_block(),
+            If => this.parse_if(),
+            While => this.parse_while(),
+            For => this.parse_for(),
+            Break => this.parse_break(),
+            Continue => this.parse_continue(),
+            Return => this.parse_return(),
+            _ => this.parse_expr_stmt(),
+        })
     }
 
     fn parse_block(&mut self) -> PResult<Stmt> {
@@ -203,6 +222,7 @@ impl<'a> Parser<'a> {
             span_start.union(&stmt.span()),
             cond,
             stmt,
+            None,
         )))
     }
 
@@ -242,17 +262,11 @@ impl<'a> Parser<'a> {
             "expect ')' after 'for' increment".into(),
         )?;
 
-        let mut stmt = self.parse_stmt()?;
-        let span = span_start.union(&stmt.span());
-
-        if let Some(increment) = increment {
-            stmt = Stmt::Block(Block::new(
-                span,
-                vec![stmt, Stmt::Expr(ExprStmt::new(increment.span(), increment))],
-            ))
-        }
+        let body = self.parse_stmt()?;
+        let span = span_start.union(&body.span());
 
-        stmt = Stmt::While(While::new(span, condition, stmt));
+        // The increment is kept separate from the body so that it still runs after `continue`
+        let mut stmt = Stmt::While(While::new(span, condition, body, increment));
 
         if let Some(initializer) = initializer {
             stmt = Stmt::Block(Block::new(span, vec![initializer, stmt]));
@@ -261,6 +275,20 @@ impl<'a> Parser<'a> {
         Ok(stmt)
     }
 
+    fn parse_break(&mut self) -> PResult<Stmt> {
+        let start = self.bump().span;
+        let semicolon = self.expect_semicolon()?;
+
+        Ok(Stmt::Break(Break::new(start.union(&semicolon.span))))
+    }
+
+    fn parse_continue(&mut self) -> PResult<Stmt> {
+        let start = self.bump().span;
+        let semicolon = self.expect_semicolon()?;
+
+        Ok(Stmt::Continue(Continue::new(start.union(&semicolon.span))))
+    }
+
     fn parse_print(&mut self) -> PResult<Stmt> {
         let start = self.bump().span;
 
@@ -303,7 +331,7 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use crate::ast::expr;
-    use crate::ast::expr::{Expr, Literal, Value};
+    use crate::ast::expr::{Assign, BinOp, Binary, Call, Expr, Lambda, Literal, Value};
     use crate::span::Span;
 
     use super::*;
@@ -416,6 +444,7 @@ mod tests {
                 Span::new(13, 21),
                 Expr::Literal(Literal::new(Span::new(19, 20), Value::Number(1.0))),
             )),
+            None,
         ));
 
         let mut parser = Parser::new(source);
@@ -424,6 +453,72 @@ mod tests {
         assert_eq!(expected, stmt);
     }
 
+    #[test]
+    fn parse_for() {
+        let source = "for (;;i = i + 1) { break; continue; }";
+        let expected = Stmt::While(While::new(
+            Span::new(0, 38),
+            Expr::Literal(Literal::new(Span::new(6, 7), Value::Boolean(true))),
+            Stmt::Block(Block::new(
+                Span::new(18, 38),
+                vec![
+                    Stmt::Break(Break::new(Span::new(20, 26))),
+                    Stmt::Continue(Continue::new(Span::new(27, 36))),
+                ],
+            )),
+            Some(Expr::Assign(Assign::new(
+                Span::new(7, 16),
+                Identifier::new(Span::new(7, 8), "i", 0),
+                Expr::Binary(Binary::new(
+                    Span::new(11, 16),
+                    BinOp::Add,
+                    Expr::Var(expr::Var::new(
+                        Span::new(11, 12),
+                        Identifier::new(Span::new(11, 12), "i", 1),
+                    )),
+                    Expr::Literal(Literal::new(Span::new(15, 16), Value::Number(1.0))),
+                )),
+            ))),
+        ));
+
+        let mut parser = Parser::new(source);
+        let stmt = parser.parse_declaration().unwrap();
+
+        assert_eq!(expected, stmt);
+    }
+
+    #[test]
+    fn parse_lambda_stmt() {
+        let source = "fun (a) {}(1);";
+        let expected = Stmt::Expr(ExprStmt::new(
+            Span::new(0, 14),
+            Expr::Call(Call::new(
+                Span::new(0, 13),
+                Expr::Lambda(Lambda::new(
+                    Span::new(0, 10),
+                    FunDecl::new(
+                        Span::new(0, 10),
+                        Identifier::new(Span::new(0, 3), "lambda", 0),
+                        vec![Identifier::new(Span::new(5, 6), "a", 1)],
+                        Vec::new(),
+                    ),
+                )),
+                vec![Expr::Literal(Literal::new(
+                    Span::new(11, 12),
+                    Value::Number(1.0),
+                ))],
+            )),
+        ));
+
+        let mut parser = Parser::new(source);
+        let stmt = parser.parse_declaration().unwrap();
+
+        assert_eq!(expected, stmt);
+        // A name after `fun` still starts a declaration
+        let mut parser = Parser::new("fun a() {}");
+        assert!(matches!(parser.parse_declaration(), Ok(Stmt::FunDecl(_))));
+    }
+
     #[test]
     fn parse_return() {
         let source = "return 5;";
@@ -462,4 +557,49 @@ mod tests {
 
         assert_eq!(expected, stmt);
     }
+
+    #[test]
+    fn rejects_code_nested_too_deeply() {
+        use crate::parser::error::ParseError;
+        use crate::parser::MAX_NESTING_DEPTH;
+
+        let n = MAX_NESTING_DEPTH * 100;
+        let sources = [
+            format!("{}print 1;{}", "{".repeat(n), "}".repeat(n)),
+            format!("print {}1{};", "(".repeat(n), ")".repeat(n)),
+            format!("print {}1;", "-".repeat(n)),
+            format!("print 1{};", " + 1".repeat(n)),
+            format!("f{};", "()".repeat(n)),
+            format!("{}return;{}", "fun f() { ".repeat(n), "}".repeat(n)),
+        ];
+
+        // The limit is chosen to fit within the main thread's stack, test threads are given less
+        // by default
+        let parse = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(move || {
+                for source in sources {
+                    let mut parser = Parser::new(&source);
+                    parser.parse();
+                    // Parsing stops rather than reporting the error again at each level
+                    assert!(matches!(
+                        parser.diagnostics(),
+                        [ParseError::TooDeeplyNested { .. }]
+                    ));
+                }
+            })
+            .unwrap();
+        parse.join().unwrap();
+
+        let n = MAX_NESTING_DEPTH / 4;
+        let source = format!(
+            "{}print 1{};{}",
+            "{".repeat(n),
+            " + 1".repeat(n),
+            "}".repeat(n)
+        );
+        let mut parser = Parser::new(&source);
+        parser.parse();
+        assert!(parser.diagnostics().is_empty());
+    }
 }
@@ -0,0 +1,185 @@
+//! Conversion of the byte offsets held by `Span`s into the lines and columns of source files.
+
+use std::fmt::{Display, Formatter};
+
+use crate::span::Span;
+
+/// A handle to a file which has been added to a `SourceMap`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FileId(usize);
+
+/// The source files of a program. Each file is parsed separately so spans are offsets into a
+/// single file and are looked up along with the `FileId` of the file they belong to.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_file(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.files.push(SourceFile::new(name, source));
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0]
+    }
+
+    /// Formats the start of `span` in the file `id` as `name:line:column`.
+    pub fn location(&self, id: FileId, span: Span) -> Location<'_> {
+        self.file(id).location(span)
+    }
+}
+
+/// A named source file along with the offset at which each of its lines start.
+#[derive(Debug)]
+pub struct SourceFile {
+    name: String,
+    source: String,
+    /// The byte offset of the start of each line, the first line starts at 0.
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        let source = source.into();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            name: name.into(),
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the 1-based line and column of the byte at `offset`, columns count characters
+    /// rather than bytes. Offsets past the end of the source are clamped to the end.
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        // The line is the last one which starts at or before the offset
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.source[self.line_starts[line]..offset].chars().count();
+
+        (line + 1, column + 1)
+    }
+
+    /// Returns the text of the 1-based `line` without its line ending.
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.source.len());
+
+        self.source[start..end].trim_end_matches(&['\n', '\r'][..])
+    }
+
+    /// The number of lines in the file, a trailing newline doesn't start a new line and an empty
+    /// file has none.
+    pub fn line_count(&self) -> usize {
+        match self.source.ends_with('\n') || self.source.is_empty() {
+            true => self.line_starts.len() - 1,
+            false => self.line_starts.len(),
+        }
+    }
+
+    /// Formats the start of `span` as `name:line:column`.
+    pub fn location(&self, span: Span) -> Location<'_> {
+        let (line, column) = self.line_column(span.range().start);
+        Location {
+            file: &self.name,
+            line,
+            column,
+        }
+    }
+}
+
+/// A position in a named source file, displayed as `name:line:column`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub file: &'a str,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+}
+
+impl Display for Location<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_offsets_to_lines_and_columns() {
+        let file = SourceFile::new("test.lox", "var a = 1;\n\nprint a;\n");
+
+        assert_eq!(file.line_column(0), (1, 1));
+        assert_eq!(file.line_column(4), (1, 5));
+        assert_eq!(file.line_column(10), (1, 11));
+        assert_eq!(file.line_column(11), (2, 1));
+        assert_eq!(file.line_column(12), (3, 1));
+        assert_eq!(file.line_column(18), (3, 7));
+        assert_eq!(file.line_column(21), (4, 1));
+        assert_eq!(file.line_column(100), (4, 1));
+
+        assert_eq!(file.line(1), "var a = 1;");
+        assert_eq!(file.line(2), "");
+        assert_eq!(file.line(3), "print a;");
+        assert_eq!(file.line_count(), 3);
+        assert_eq!(SourceFile::new("empty.lox", "").line_count(), 0);
+        assert_eq!(file.location(Span::new(18, 19)).to_string(), "test.lox:3:7");
+    }
+
+    #[test]
+    fn counts_characters_in_columns() {
+        let source = "print \"héllo\";\r\nprint \"😀\" + x;";
+        let file = SourceFile::new("utf8.lox", source);
+
+        let x = source.find('x').unwrap();
+        assert_eq!(file.line_column(x), (2, 13));
+        assert_eq!(file.line_column(source.find(';').unwrap()), (1, 14));
+        assert_eq!(file.line(1), "print \"héllo\";");
+    }
+
+    #[test]
+    fn maps_multiple_files() {
+        let mut map = SourceMap::new();
+        let main = map.add_file("main.lox", "print 1;\nprint 2;");
+        let lib = map.add_file("lib.lox", "\n\n  fun f() {}");
+
+        assert_eq!(map.file(main).name(), "main.lox");
+        assert_eq!(map.file(lib).source(), "\n\n  fun f() {}");
+        assert_eq!(
+            map.location(main, Span::new(15, 16)).to_string(),
+            "main.lox:2:7"
+        );
+        assert_eq!(
+            map.location(lib, Span::new(4, 7)),
+            Location {
+                file: "lib.lox",
+                line: 3,
+                column: 3
+            }
+        );
+    }
+}
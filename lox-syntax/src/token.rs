@@ -18,7 +18,7 @@ impl Token {
 #[macro_export]
 macro_rules! token {
     ($kind:expr, $lo:expr, $offset:expr) => {
-        crate::token::Token::new($kind, crate::span::Span::offset($lo, $offset))
+        $crate::token::Token::new($kind, $crate::span::Span::offset($lo, $offset))
     };
 }
 
@@ -29,6 +29,8 @@ pub enum TokenKind {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -54,7 +56,9 @@ pub enum TokenKind {
 
     // Keywords
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -110,6 +114,8 @@ impl Display for TokenKind {
             RightParen => write!(f, ")"),
             LeftBrace => write!(f, "{{"),
             RightBrace => write!(f, "}}"),
+            LeftBracket => write!(f, "["),
+            RightBracket => write!(f, "]"),
             Comma => write!(f, ","),
             Dot => write!(f, "."),
             Minus => write!(f, "-"),
@@ -129,7 +135,9 @@ impl Display for TokenKind {
             String(s) => write!(f, "\"{}\"", s),
             Number(n) => write!(f, "{}", n),
             And => write!(f, "and"),
+            Break => write!(f, "break"),
             Class => write!(f, "class"),
+            Continue => write!(f, "continue"),
             Else => write!(f, "else"),
             False => write!(f, "false"),
             Fun => write!(f, "fun"),
@@ -2,6 +2,8 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
+use crate::report::ErrorFormat;
+
 #[derive(Debug, Parser)]
 #[clap(name = "rlox")]
 #[clap(author = "Olly Swanson <olly.swanson95@gmail.com")]
@@ -17,6 +19,20 @@ pub struct Args {
     /// Run tree-walk interpreter
     #[clap(short, long)]
     pub tree_walk: bool,
+
+    /// Compile the script to bytecode and write it to OUTPUT instead of running it, compiled
+    /// scripts are run by passing a .loxc file as the script
+    #[clap(short, long, value_name = "OUTPUT", conflicts_with = "tree-walk")]
+    pub output: Option<PathBuf>,
+
+    /// Print the stack and each instruction as the bytecode interpreter executes it, requires
+    /// rlox to be built with the trace feature
+    #[clap(long, conflicts_with_all = &["tree-walk", "output"])]
+    pub trace: bool,
+
+    /// How errors are printed, json prints an object per error on its own line
+    #[clap(long, arg_enum, value_name = "FORMAT", default_value = "human")]
+    pub error_format: ErrorFormat,
 }
 
 pub fn get_args() -> Args {
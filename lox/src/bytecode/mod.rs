@@ -1,11 +1,107 @@
-use bytecode::{Chunk, OpCode, Value};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
 
-pub fn run() -> Result<(), Box<dyn std::error::Error>> {
-    let mut chunk = Chunk::new();
-    let i = chunk.push_constant(Value::Number(1.0));
-    chunk.push_op(OpCode::Constant(i));
-    chunk.push_op(OpCode::Return);
-    chunk.disassemble("test chunk");
+use bytecode::{loader, Compiler, Function, Vm};
+use lox_syntax::{optimize::optimize, Parser};
 
+use crate::report::{ErrorFormat, Reporter};
+
+/// Runs the script at `path`, which is either Lox source or a script compiled with
+/// `compile_source`. If `trace` is set each instruction is printed as it is executed.
+pub fn run_source(
+    path: &Path,
+    trace: bool,
+    format: ErrorFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut vm = Vm::new();
+    if trace {
+        enable_trace(&mut vm)?;
+    }
+
+    let name = path.display().to_string();
+    let (function, reporter) = if path.extension().is_some_and(|ext| ext == "loxc") {
+        // Errors in compiled scripts can only be reported by line as the source isn't available
+        let reporter = Reporter::new(name, "").with_format(format);
+        match loader::load(&mut File::open(path)?) {
+            Ok(function) => (function, reporter),
+            Err(e) => {
+                reporter.report(&e);
+                return Ok(());
+            }
+        }
+    } else {
+        let reporter = Reporter::new(name, fs::read_to_string(path)?).with_format(format);
+        match compile(&reporter) {
+            Some(function) => (function, reporter),
+            None => return Ok(()),
+        }
+    };
+
+    if let Err(e) = vm.interpret(function) {
+        reporter.report(&e);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "trace")]
+fn enable_trace(vm: &mut Vm) -> Result<(), Box<dyn std::error::Error>> {
+    vm.set_trace_execution(true);
     Ok(())
 }
+
+#[cfg(not(feature = "trace"))]
+fn enable_trace(_: &mut Vm) -> Result<(), Box<dyn std::error::Error>> {
+    Err("tracing requires rlox to be built with the trace feature".into())
+}
+
+/// Compiles the script at `path` and writes the bytecode to `output` in the `.loxc` format.
+pub fn compile_source(
+    path: &Path,
+    output: &Path,
+    format: ErrorFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter =
+        Reporter::new(path.display().to_string(), fs::read_to_string(path)?).with_format(format);
+    if let Some(function) = compile(&reporter) {
+        let mut writer = BufWriter::new(File::create(output)?);
+        function.chunk.write_to(&mut writer)?;
+    }
+
+    Ok(())
+}
+
+/// Compiles the source of `reporter`, reporting any diagnostics and returning `None` if it
+/// contains errors.
+fn compile(reporter: &Reporter) -> Option<Function> {
+    let mut parser = Parser::new(reporter.source());
+    let statements = parser.parse();
+
+    if !parser.diagnostics().is_empty() {
+        for diagnostic in parser.diagnostics().iter() {
+            reporter.report(diagnostic);
+        }
+        return None;
+    }
+
+    let mut compiler = Compiler::new(reporter.source());
+    // Dead branches are only removed once the compiler has checked them for errors
+    compiler.check(&statements);
+    if !compiler.diagnostics().is_empty() {
+        for diagnostic in compiler.diagnostics().iter() {
+            reporter.report(diagnostic);
+        }
+        return None;
+    }
+
+    let function = compiler.compile(&optimize(statements));
+    if !compiler.diagnostics().is_empty() {
+        for diagnostic in compiler.diagnostics().iter() {
+            reporter.report(diagnostic);
+        }
+        return None;
+    }
+
+    Some(function)
+}
@@ -1,4 +1,5 @@
 pub mod args;
 pub mod bytecode;
+pub mod report;
 pub mod run;
 pub mod tree_walk;
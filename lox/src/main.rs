@@ -1,7 +1,12 @@
+use std::process;
+
 use lox::args::get_args;
 use lox::run::run_lox;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() {
     let args = get_args();
-    run_lox(args)
+    if let Err(e) = run_lox(args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,101 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+use clap::ArgEnum;
+use lox_syntax::diagnostic::{Diagnostic, Renderer};
+use lox_syntax::source_map::SourceFile;
+use lox_syntax::span::Span;
+use serde_json::{json, Value};
+
+/// How diagnostics are printed.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ArgEnum)]
+pub enum ErrorFormat {
+    /// The source of each diagnostic with the spans it refers to underlined.
+    #[default]
+    Human,
+    /// A JSON object per diagnostic on its own line.
+    Json,
+}
+
+/// Prints the diagnostics of a source file to stderr. Human readable output is highlighted when
+/// stderr is a terminal unless `NO_COLOR` is set.
+pub struct Reporter {
+    file: SourceFile,
+    format: ErrorFormat,
+    colour: bool,
+}
+
+impl Reporter {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            file: SourceFile::new(name, source),
+            format: ErrorFormat::default(),
+            colour: io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    pub fn with_format(mut self, format: ErrorFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn source(&self) -> &str {
+        self.file.source()
+    }
+
+    pub fn report(&self, diagnostic: impl Into<Diagnostic>) {
+        let diagnostic = diagnostic.into();
+        match self.format {
+            ErrorFormat::Human => {
+                let renderer = Renderer::new(&self.file).with_colour(self.colour);
+                eprint!("{}", renderer.render(&diagnostic));
+            }
+            ErrorFormat::Json => eprintln!("{}", self.to_json(&diagnostic)),
+        }
+    }
+
+    /// Converts `diagnostic` to an object with the location of its spans in the file, fields
+    /// which aren't known are `null`.
+    fn to_json(&self, diagnostic: &Diagnostic) -> Value {
+        let (line, column) = match (diagnostic.span, diagnostic.line) {
+            (Some(span), _) => {
+                let (line, column) = self.file.line_column(span.range().start);
+                (Some(line), Some(column))
+            }
+            (None, line) => (line, None),
+        };
+
+        let labels: Vec<_> = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                let (line, column) = self.file.line_column(label.span.range().start);
+                json!({
+                    "span": span_json(label.span),
+                    "line": line,
+                    "column": column,
+                    "message": label.message,
+                })
+            })
+            .collect();
+
+        json!({
+            "file": self.file.name(),
+            "span": diagnostic.span.map(span_json),
+            "line": line,
+            "column": column,
+            "severity": diagnostic.severity.to_string(),
+            "code": diagnostic.code,
+            "message": diagnostic.message,
+            "labels": labels,
+            "notes": diagnostic.notes,
+            "help": diagnostic.help,
+        })
+    }
+}
+
+/// The byte offsets of `span`, the end is exclusive.
+fn span_json(span: Span) -> Value {
+    let range = span.range();
+    json!({ "start": range.start, "end": range.end })
+}
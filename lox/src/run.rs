@@ -5,8 +5,12 @@ use crate::args::Args;
 
 pub fn run_lox(args: Args) -> Result<(), Box<dyn std::error::Error>> {
     match (args.tree_walk, args.script) {
-        (true, Some(path)) => tree_walk::run_source(&path),
-        (true, None) => tree_walk::run_repl(),
-        (false, _) => bytecode::run(),
+        (true, Some(path)) => tree_walk::run_source(&path, args.error_format),
+        (true, None) => tree_walk::run_repl(args.error_format),
+        (false, Some(path)) => match args.output {
+            Some(output) => bytecode::compile_source(&path, &output, args.error_format),
+            None => bytecode::run_source(&path, args.trace, args.error_format),
+        },
+        (false, None) => Err("a script is required when using the bytecode interpreter".into()),
     }
 }
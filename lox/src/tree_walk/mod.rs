@@ -1,22 +1,24 @@
 use std::fs;
 use std::path::Path;
 
-use lox_syntax::Parser;
+use lox_syntax::{optimize::optimize, Parser};
 use tree_walk::{Interpreter, Resolver};
 
+use crate::report::{ErrorFormat, Reporter};
+
 mod repl;
 
-pub fn run_source(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let source = fs::read_to_string(path)?;
+pub fn run_source(path: &Path, format: ErrorFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter =
+        Reporter::new(path.display().to_string(), fs::read_to_string(path)?).with_format(format);
 
     let mut interpreter = Interpreter::new();
-    let mut parser = Parser::new(&source);
+    let mut parser = Parser::new(reporter.source());
     let statements = parser.parse();
-    let diagnostics = parser.diagnostics();
 
     if !parser.diagnostics().is_empty() {
-        for diagnostic in diagnostics.iter() {
-            eprintln!("{}", diagnostic);
+        for diagnostic in parser.diagnostics().iter() {
+            reporter.report(diagnostic);
         }
         return Ok(());
     }
@@ -26,21 +28,23 @@ pub fn run_source(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
 
     if !resolver.diagnostics().is_empty() {
         for diagnostic in resolver.diagnostics().iter() {
-            eprintln!("{}", diagnostic)
+            reporter.report(diagnostic);
         }
         return Ok(());
     }
 
-    match interpreter.interpret(&statements) {
-        Ok(_) => {}
-        Err(e) => eprintln!("{}", e),
+    // Dead branches are only removed once the resolver has checked them for errors
+    let statements = optimize(statements);
+
+    if let Err(e) = interpreter.interpret(&statements) {
+        reporter.report(&e);
     }
 
     Ok(())
 }
 
-pub fn run_repl() -> Result<(), Box<dyn std::error::Error>> {
-    let mut repl = repl::Repl::new();
+pub fn run_repl(format: ErrorFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = repl::Repl::new(format);
     repl.start()?;
 
     Ok(())
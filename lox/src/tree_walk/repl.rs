@@ -2,9 +2,11 @@ use std::io;
 use std::io::{BufRead, Write};
 
 use lox_syntax::parser::ParserState;
-use lox_syntax::Parser;
+use lox_syntax::{optimize::optimize, Parser};
 use tree_walk::{Interpreter, Resolver};
 
+use crate::report::{ErrorFormat, Reporter};
+
 #[derive(Debug, Default)]
 pub struct Repl {
     interpreter: Interpreter,
@@ -13,14 +15,16 @@ pub struct Repl {
     // TODO: leaky abstraction, but currently necessary when running the REPL, is there a better
     // way to pass state between parsers or any way to avoid needing state completely?
     parser_state: ParserState,
+    error_format: ErrorFormat,
 }
 
 impl Repl {
-    pub fn new() -> Self {
+    pub fn new(error_format: ErrorFormat) -> Self {
         Self {
             interpreter: Interpreter::new(),
             curr_src: "".to_owned(),
             parser_state: ParserState::new(),
+            error_format,
         }
     }
 
@@ -49,13 +53,17 @@ impl Repl {
         let mut parser = Parser::new(&self.curr_src).with_state(self.parser_state);
         let statements = parser.parse();
         let diagnostics = parser.diagnostics();
+        // Spans are offsets into the current input, errors raised in functions declared by
+        // earlier inputs will underline the wrong source
+        let reporter =
+            Reporter::new("<repl>", self.curr_src.as_str()).with_format(self.error_format);
 
         if !parser.diagnostics().is_empty() {
             if parser.diagnostics().iter().all(|e| e.allows_continuation()) {
                 return;
             } else {
                 for diagnostic in diagnostics.iter() {
-                    eprintln!("{}", diagnostic);
+                    reporter.report(diagnostic);
                 }
                 return;
             }
@@ -69,14 +77,16 @@ impl Repl {
 
         if !resolver.diagnostics().is_empty() {
             for diagnostic in resolver.diagnostics().iter() {
-                eprintln!("{}", diagnostic)
+                reporter.report(diagnostic);
             }
             return;
         }
 
-        match self.interpreter.interpret(&statements) {
-            Ok(_) => {}
-            Err(e) => eprintln!("{}", e),
+        // Dead branches are only removed once the resolver has checked them for errors
+        let statements = optimize(statements);
+
+        if let Err(e) = self.interpreter.interpret(&statements) {
+            reporter.report(&e);
         }
     }
 }
@@ -2,14 +2,24 @@ use std::borrow::Cow;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 
+use lox_syntax::diagnostic::Diagnostic;
+use lox_syntax::span::Span;
+
 pub type RResult<T> = Result<T, RuntimeError>;
 
+/// The number of innermost calls included in the trace of a `StackOverflow`.
+const STACK_OVERFLOW_TRACE_LEN: usize = 8;
+
 #[derive(Debug)]
 pub enum RuntimeError {
     TypeError(TypeError),
     DivisionByZero,
     Undefined(Undefined),
+    IndexOutOfRange(IndexOutOfRange),
     ReturnOutsideFunction,
+    /// The maximum call or nesting depth was exceeded, the trace of the error only holds the
+    /// innermost calls.
+    StackOverflow,
 }
 
 #[derive(Debug)]
@@ -22,17 +32,163 @@ pub struct Undefined {
     pub(crate) message: Cow<'static, str>,
 }
 
+#[derive(Debug)]
+pub struct IndexOutOfRange {
+    pub(crate) message: Cow<'static, str>,
+}
+
+/// A `RuntimeError` along with where it was raised and the calls which were active at the time.
+#[derive(Debug)]
+pub struct TracedError {
+    pub error: RuntimeError,
+    /// The innermost expression, or statement if the error wasn't raised by an expression, being
+    /// executed when the error was raised.
+    pub span: Option<Span>,
+    /// The active calls, innermost first.
+    pub trace: Vec<TraceFrame>,
+    /// The span being executed in the call the error is currently unwinding through.
+    location: Option<Span>,
+}
+
+/// The function and span which was executing in an active call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    /// The name of the function, empty for the top level script.
+    pub function: String,
+    /// The expression which raised the error in the innermost call and the call to the next
+    /// innermost function in the others.
+    pub span: Span,
+}
+
+impl TracedError {
+    /// Attaches `span` as the location of the error in the current call if it hasn't already
+    /// been given one by an inner expression.
+    pub(crate) fn at(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self.location.get_or_insert(span);
+        self
+    }
+
+    /// Records that the error unwound out of a call to `function`, an empty name is used for the
+    /// script.
+    pub(crate) fn unwind(&mut self, function: &str) {
+        let span = match self.location.take() {
+            Some(span) => span,
+            None => return,
+        };
+
+        if matches!(self.error, RuntimeError::StackOverflow)
+            && self.trace.len() >= STACK_OVERFLOW_TRACE_LEN
+        {
+            return;
+        }
+
+        self.trace.push(TraceFrame {
+            function: function.to_owned(),
+            span,
+        });
+    }
+}
+
+impl From<RuntimeError> for TracedError {
+    fn from(error: RuntimeError) -> Self {
+        Self {
+            error,
+            span: None,
+            trace: Vec::new(),
+            location: None,
+        }
+    }
+}
+
+impl RuntimeError {
+    /// The stable code identifying the kind of error, shared with the equivalent errors raised by
+    /// the bytecode VM.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeError::TypeError(_) => "E0301",
+            RuntimeError::DivisionByZero => "E0302",
+            RuntimeError::Undefined(_) => "E0303",
+            RuntimeError::StackOverflow => "E0304",
+            RuntimeError::ReturnOutsideFunction => "E0305",
+            RuntimeError::IndexOutOfRange(_) => "E0307",
+        }
+    }
+}
+
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             RuntimeError::TypeError(TypeError { message }) => f.write_str(message),
             RuntimeError::DivisionByZero => f.write_str("division by zero"),
             RuntimeError::Undefined(Undefined { message }) => f.write_str(message),
+            RuntimeError::IndexOutOfRange(IndexOutOfRange { message }) => f.write_str(message),
             RuntimeError::ReturnOutsideFunction => {
                 f.write_str("return must be used within a function")
             }
+            RuntimeError::StackOverflow => f.write_str("stack overflow"),
         }
     }
 }
 
 impl Error for RuntimeError {}
+
+impl Display for TracedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)?;
+        for frame in self.trace.iter() {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for TraceFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.function.is_empty() {
+            write!(f, "[{}] in script", self.span)
+        } else {
+            write!(f, "[{}] in {}()", self.span, self.function)
+        }
+    }
+}
+
+/// The error is reported at the expression which raised it with the call made by each active
+/// function labelled.
+impl From<&TracedError> for Diagnostic {
+    fn from(error: &TracedError) -> Self {
+        let mut diagnostic =
+            Diagnostic::error(error.error.to_string()).with_code(error.error.code());
+        if let Some(span) = error.span {
+            diagnostic = diagnostic.with_span(span);
+        }
+
+        // The first frame is the innermost call which is at the span of the error itself
+        for (callee, frame) in error.trace.iter().zip(error.trace.iter().skip(1)) {
+            // Recursive calls are made from the same place, they're only labelled once
+            if diagnostic.span == Some(frame.span)
+                || diagnostic
+                    .labels
+                    .iter()
+                    .any(|label| label.span == frame.span)
+            {
+                continue;
+            }
+            diagnostic =
+                diagnostic.with_label(frame.span, format!("call to {}()", callee.function));
+        }
+
+        match error.error {
+            RuntimeError::StackOverflow => {
+                diagnostic.with_help("check that recursive functions have a base case")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl Error for TracedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
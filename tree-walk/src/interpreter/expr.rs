@@ -1,34 +1,53 @@
+use std::cell::RefCell;
 use std::rc::Rc;
 
 use lox_syntax::ast::expr::*;
 
 use crate::interpreter::error::{RuntimeError, TypeError, Undefined};
+use crate::interpreter::value::function::{LoxFunction, LoxFunctionType};
+use crate::interpreter::value::list::to_position;
 use crate::interpreter::value::RuntimeValue;
-use crate::interpreter::{CFResult, ControlFlow, Interpreter};
+use crate::interpreter::{CFResult, Interpreter};
 
 impl Interpreter {
+    /// Evaluates `expr`, errors raised by the expression which don't already have a span are
+    /// given the span of `expr`.
     pub fn evaluate_expr(&mut self, expr: &Expr) -> CFResult<RuntimeValue> {
         use Expr::*;
-        match expr {
+        let result = self.nested(|this| match expr {
             Literal(literal) => Ok(RuntimeValue::from(&literal.value)),
-            Var(v) => self.evaluate_var_expr(v),
-            Grouping(g) => self.evaluate_expr(&g.expr),
-            Binary(b) => self.evaluate_binary_expression(b),
-            Logical(l) => self.evaluate_logical_expression(l),
-            Unary(u) => self.evaluate_unary_expression(u),
-            Assign(a) => self.evaluate_assign(a),
-            Call(c) => self.evaluate_call(c),
-            Get(g) => self.evaluate_get(g),
-            Set(s) => self.evaluate_set(s),
-            This(t) => self.evaluate_this(t),
-            Super(s) => self.evaluate_super(s),
-        }
+            Var(v) => this.evaluate_var_expr(v),
+            Grouping(g) => this.evaluate_expr(&g.expr),
+            Binary(b) => this.evaluate_binary_expression(b),
+            Logical(l) => this.evaluate_logical_expression(l),
+            Unary(u) => this.evaluate_unary_expression(u),
+            Assign(a) => this.evaluate_assign(a),
+            Call(c) => this.evaluate_call(c),
+            Get(g) => this.evaluate_get(g),
+            Set(s) => this.evaluate_set(s),
+            This(t) => this.evaluate_this(t),
+            Super(s) => this.evaluate_super(s),
+            Lambda(l) => Ok(this.evaluate_lambda(l)),
+            List(l) => this.evaluate_list(l),
+            Index(i) => this.evaluate_index(i),
+            SetIndex(s) => this.evaluate_set_index(s),
+        });
+
+        result.map_err(|e| e.at(expr.span()))
     }
 
     fn evaluate_var_expr(&self, var_expr: &Var) -> CFResult<RuntimeValue> {
         self.get_variable(&var_expr.id)
     }
 
+    fn evaluate_lambda(&self, lambda: &Lambda) -> RuntimeValue {
+        RuntimeValue::Function(Rc::new(LoxFunction::new(
+            &lambda.decl,
+            self.environment.clone(),
+            LoxFunctionType::Function,
+        )))
+    }
+
     fn evaluate_this(&self, this_expr: &This) -> CFResult<RuntimeValue> {
         self.get_variable(&this_expr.id)
     }
@@ -129,11 +148,10 @@ impl Interpreter {
 
         match object {
             RuntimeValue::Object(instance) => instance.get(&get.property.name),
-            _ => Err(ControlFlow::RuntimeError(RuntimeError::TypeError(
-                TypeError {
-                    message: "only instances have properties".into(),
-                },
-            ))),
+            _ => Err(RuntimeError::TypeError(TypeError {
+                message: "only instances have properties".into(),
+            })
+            .into()),
         }
     }
 
@@ -145,11 +163,55 @@ impl Interpreter {
                 let value = self.evaluate_expr(&set.value)?;
                 instance.set(&set.property.name, value)
             }
-            _ => Err(ControlFlow::RuntimeError(RuntimeError::TypeError(
-                TypeError {
-                    message: "only instances have properties".into(),
-                },
-            ))),
+            _ => Err(RuntimeError::TypeError(TypeError {
+                message: "only instances have properties".into(),
+            })
+            .into()),
+        }
+    }
+
+    fn evaluate_list(&mut self, list: &List) -> CFResult<RuntimeValue> {
+        let items = list
+            .items
+            .iter()
+            .map(|item| self.evaluate_expr(item))
+            .collect::<CFResult<_>>()?;
+
+        Ok(RuntimeValue::List(Rc::new(RefCell::new(items))))
+    }
+
+    fn evaluate_index(&mut self, index: &Index) -> CFResult<RuntimeValue> {
+        let object = self.evaluate_expr(&index.object)?;
+        let position = self.evaluate_expr(&index.index)?;
+
+        match object {
+            RuntimeValue::List(items) => {
+                let items = items.borrow();
+                let position = to_position(&position, items.len(), false)?;
+                Ok(items[position].clone())
+            }
+            _ => Err(RuntimeError::TypeError(TypeError {
+                message: "only lists can be indexed".into(),
+            })
+            .into()),
+        }
+    }
+
+    fn evaluate_set_index(&mut self, set: &SetIndex) -> CFResult<RuntimeValue> {
+        let object = self.evaluate_expr(&set.object)?;
+        let position = self.evaluate_expr(&set.index)?;
+
+        match object {
+            RuntimeValue::List(items) => {
+                let value = self.evaluate_expr(&set.value)?;
+                let position = to_position(&position, items.borrow().len(), false)?;
+                items.borrow_mut()[position] = value.clone();
+                Ok(value)
+            }
+            _ => Err(RuntimeError::TypeError(TypeError {
+                message: "only lists can be indexed".into(),
+            })
+            .into()),
         }
     }
 
@@ -170,9 +232,10 @@ impl Interpreter {
                 .find_method(&super_expr.method.name)
                 .map(|mtd| RV::Function(Rc::new(mtd.bind(this))))
                 .ok_or_else(|| {
-                    ControlFlow::RuntimeError(RuntimeError::Undefined(Undefined {
+                    RuntimeError::Undefined(Undefined {
                         message: format!("undefined property {}", super_expr.method.name).into(),
-                    }))
+                    })
+                    .into()
                 })
         } else {
             unreachable!()
@@ -185,9 +248,7 @@ fn evaluate_arithmetic_expression(l: f64, r: f64, op: BinOp) -> CFResult<Runtime
     use RuntimeValue::*;
 
     match (l, r, op) {
-        (_l, r, Divide) if r == 0f64 => {
-            Err(ControlFlow::RuntimeError(RuntimeError::DivisionByZero))
-        }
+        (_l, 0f64, Divide) => Err(RuntimeError::DivisionByZero.into()),
         (l, r, Divide) => Ok(Number(l / r)),
         (l, r, Multiply) => Ok(Number(l * r)),
         (l, r, Add) => Ok(Number(l + r)),
@@ -3,11 +3,12 @@ use std::rc::Rc;
 
 use environment::Environment;
 use error::RResult;
-use error::RuntimeError;
 use lox_syntax::ast::stmt::Stmt;
 use lox_syntax::ast::IdentifierId;
+use lox_syntax::span::Span;
 use lox_syntax::Identifier;
 use value::function::Clock;
+use value::list::ListNative;
 use value::RuntimeValue;
 
 mod environment;
@@ -16,17 +17,43 @@ mod expr;
 mod stmt;
 mod value;
 
+pub use error::{RuntimeError, TraceFrame, TracedError};
+
 pub type CFResult<T> = Result<T, ControlFlow>;
 
+/// The number of nested calls allowed by default before a `StackOverflow` is raised.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// The number of statements and expressions, including those of active calls, which may be
+/// nested before a `StackOverflow` is raised. Each of these is evaluated recursively on the
+/// native stack, so it's this rather than the call depth which bounds the stack's use. A level
+/// takes at most around 3KB in a debug build, which keeps the interpreter well within the 8MB
+/// stack of the main thread.
+const MAX_NESTING_DEPTH: usize = 2_000;
+
 #[derive(Debug)]
 pub enum ControlFlow {
     Return(RuntimeValue),
-    RuntimeError(RuntimeError),
+    /// Exits the innermost loop, the resolver ensures that it's only raised within a loop.
+    Break,
+    /// Ends the current iteration of the innermost loop.
+    Continue,
+    RuntimeError(TracedError),
+}
+
+impl ControlFlow {
+    /// Attaches `span` to a runtime error which hasn't been given one yet.
+    fn at(self, span: Span) -> Self {
+        match self {
+            ControlFlow::RuntimeError(e) => ControlFlow::RuntimeError(e.at(span)),
+            cf => cf,
+        }
+    }
 }
 
 impl From<RuntimeError> for ControlFlow {
     fn from(e: RuntimeError) -> Self {
-        ControlFlow::RuntimeError(e)
+        ControlFlow::RuntimeError(e.into())
     }
 }
 
@@ -35,6 +62,11 @@ pub struct Interpreter {
     environment: Environment,
     globals: Environment,
     locals: HashMap<IdentifierId, usize>,
+    /// The number of functions currently being called.
+    call_depth: usize,
+    max_call_depth: usize,
+    /// The number of statements and expressions currently being evaluated.
+    nesting_depth: usize,
 }
 
 impl Interpreter {
@@ -42,21 +74,62 @@ impl Interpreter {
         Default::default()
     }
 
-    pub fn interpret(&mut self, statements: &[Stmt]) -> RResult<()> {
+    /// Executes `statements`, if a runtime error occurs it is returned along with a trace of the
+    /// calls which were active at the time.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), TracedError> {
         for stmt in statements {
-            match self.execute_stmt(stmt) {
-                Ok(_) => {}
+            let mut error = match self.execute_stmt(stmt) {
+                Ok(_) => continue,
                 Err(ControlFlow::Return(_)) => {
-                    return Err(RuntimeError::ReturnOutsideFunction);
+                    TracedError::from(RuntimeError::ReturnOutsideFunction).at(stmt.span())
                 }
-                Err(ControlFlow::RuntimeError(e)) => {
-                    return Err(e);
+                Err(ControlFlow::RuntimeError(e)) => e,
+                Err(ControlFlow::Break | ControlFlow::Continue) => {
+                    unreachable!(
+                        "loop control outside of a loop should be rejected by the resolver"
+                    )
                 }
-            }
+            };
+
+            error.unwind("");
+            return Err(error);
         }
         Ok(())
     }
 
+    /// Sets the number of nested calls allowed before a `StackOverflow` is raised.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Records a call to a function, failing if it would exceed the maximum call depth. Every
+    /// successful call must be followed by `exit_call` once the function returns.
+    fn enter_call(&mut self) -> RResult<()> {
+        if self.call_depth >= self.max_call_depth {
+            return Err(RuntimeError::StackOverflow);
+        }
+
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    fn exit_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    /// Evaluates `f` one level deeper in the nesting of statements and expressions, raising a
+    /// `StackOverflow` instead if the native stack could be exhausted.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> CFResult<T>) -> CFResult<T> {
+        if self.nesting_depth >= MAX_NESTING_DEPTH {
+            return Err(RuntimeError::StackOverflow.into());
+        }
+
+        self.nesting_depth += 1;
+        let result = f(self);
+        self.nesting_depth -= 1;
+        result
+    }
+
     pub fn resolve(&mut self, id: &Identifier, depth: usize) {
         self.locals.insert(id.id, depth);
     }
@@ -75,11 +148,87 @@ impl Default for Interpreter {
         let mut globals = Environment::new();
         let environment = globals.clone();
         globals.define("clock", RuntimeValue::Function(Rc::new(Clock {})));
+        for native in ListNative::ALL {
+            globals.define(native.name(), RuntimeValue::Function(Rc::new(native)));
+        }
 
         Self {
             environment,
             globals,
             locals: HashMap::new(),
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            nesting_depth: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use lox_syntax::Parser;
+
+    use super::*;
+    use crate::Resolver;
+
+    /// The usual size of the main thread's stack, which `MAX_NESTING_DEPTH` is chosen to fit
+    /// within. Test threads are given less by default.
+    const MAIN_THREAD_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+    /// Interprets `source` on a thread with as much stack as the main thread, returning the
+    /// runtime error it raised.
+    fn interpret(source: &'static str, max_call_depth: usize) -> Result<(), RuntimeError> {
+        let run = move || {
+            let mut parser = Parser::new(source);
+            let stmts = parser.parse();
+            assert!(parser.diagnostics().is_empty());
+
+            let mut interpreter = Interpreter::new();
+            interpreter.set_max_call_depth(max_call_depth);
+            let mut resolver = Resolver::new(&mut interpreter);
+            resolver.resolve(&stmts);
+            assert!(resolver.diagnostics().is_empty());
+
+            interpreter.interpret(&stmts).map_err(|e| e.error)
+        };
+
+        thread::Builder::new()
+            .stack_size(MAIN_THREAD_STACK_SIZE)
+            .spawn(run)
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn raises_stack_overflow_for_deep_recursion() {
+        let sources = [
+            "fun f(n) { { { { { return f(n - 1) + 1; } } } } } f(0);",
+            "class A { m(n) { var g = fun(k) { return this.m(k); }; return g(n + 1); } } A().m(0);",
+            "class A { init() { this.a = A(); } } A();",
+        ];
+
+        for source in sources {
+            assert!(matches!(
+                interpret(source, DEFAULT_MAX_CALL_DEPTH),
+                Err(RuntimeError::StackOverflow)
+            ));
+            // The nesting depth is limited even when the call depth isn't
+            assert!(matches!(
+                interpret(source, usize::MAX),
+                Err(RuntimeError::StackOverflow)
+            ));
+        }
+    }
+
+    #[test]
+    fn limits_the_call_depth() {
+        let source = "fun f(n) { if (n == 0) return 0; return f(n - 1) + 1; } f(500);";
+        assert!(interpret(source, DEFAULT_MAX_CALL_DEPTH).is_ok());
+        assert!(matches!(
+            interpret(source, 100),
+            Err(RuntimeError::StackOverflow)
+        ));
+    }
+}
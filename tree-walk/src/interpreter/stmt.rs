@@ -17,20 +17,25 @@ use super::value::RuntimeValue;
 use super::{CFResult, Interpreter};
 
 impl Interpreter {
+    /// Executes `stmt`, errors which weren't raised by an expression are given the span of `stmt`.
     pub fn execute_stmt(&mut self, stmt: &Stmt) -> CFResult<()> {
         use Stmt::*;
 
-        match stmt {
-            Var(v) => self.execute_var_stmt(v),
-            Print(p) => self.execute_print_stmt(p),
-            Expr(s) => self.execute_expr_stmt(s),
-            Block(b) => self.execute_block_stmt(b),
-            If(i) => self.execute_if_stmt(i),
-            While(w) => self.execute_while_stmt(w),
-            FunDecl(f) => self.execute_fun_decl(f),
-            Return(r) => self.execute_return_stmt(r),
-            ClassDecl(c) => self.execute_class_decl(c),
-        }
+        let result = self.nested(|this| match stmt {
+            Var(v) => this.execute_var_stmt(v),
+            Print(p) => this.execute_print_stmt(p),
+            Expr(s) => this.execute_expr_stmt(s),
+            Block(b) => this.execute_block_stmt(b),
+            If(i) => this.execute_if_stmt(i),
+            While(w) => this.execute_while_stmt(w),
+            Break(_) => Err(ControlFlow::Break),
+            Continue(_) => Err(ControlFlow::Continue),
+            FunDecl(f) => this.execute_fun_decl(f),
+            Return(r) => this.execute_return_stmt(r),
+            ClassDecl(c) => this.execute_class_decl(c),
+        });
+
+        result.map_err(|e| e.at(stmt.span()))
     }
 
     fn execute_block_stmt(&mut self, block: &Block) -> CFResult<()> {
@@ -59,7 +64,15 @@ impl Interpreter {
 
     fn execute_while_stmt(&mut self, while_stmt: &While) -> CFResult<()> {
         while self.evaluate_expr(&while_stmt.cond)?.is_truthy() {
-            self.execute_stmt(&while_stmt.stmt)?;
+            match self.execute_stmt(&while_stmt.stmt) {
+                Ok(_) | Err(ControlFlow::Continue) => {}
+                Err(ControlFlow::Break) => break,
+                Err(e) => return Err(e),
+            }
+
+            if let Some(increment) = &while_stmt.increment {
+                self.evaluate_expr(increment)?;
+            }
         }
 
         Ok(())
@@ -112,11 +125,9 @@ impl Interpreter {
                 let super_class = self.get_variable(super_class)?;
                 match super_class {
                     RuntimeValue::Class(class) => Ok(class),
-                    _ => Err(ControlFlow::RuntimeError(RuntimeError::TypeError(
-                        TypeError {
-                            message: "superclass must be a class".into(),
-                        },
-                    ))),
+                    _ => Err(ControlFlow::from(RuntimeError::TypeError(TypeError {
+                        message: "superclass must be a class".into(),
+                    }))),
                 }
             })
             .transpose()?;
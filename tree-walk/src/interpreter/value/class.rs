@@ -6,7 +6,6 @@ use std::rc::Rc;
 use crate::interpreter::{
     error::{RuntimeError, Undefined},
     value::function::LoxFunction,
-    ControlFlow,
 };
 
 use super::{CFResult, Callable, RuntimeValue};
@@ -95,9 +94,10 @@ impl Instance {
             .find_method(property_name)
             .map(|mtd| RuntimeValue::Function(Rc::new(mtd.bind(RuntimeValue::Object(self)))))
             .ok_or_else(|| {
-                ControlFlow::RuntimeError(RuntimeError::Undefined(Undefined {
+                RuntimeError::Undefined(Undefined {
                     message: format!("undefined property {}", property_name).into(),
-                }))
+                })
+                .into()
             })
     }
 
@@ -76,6 +76,10 @@ impl LoxFunction {
         bindings.define("this", value);
         Self::new(&self.decl, bindings, self.function_type)
     }
+
+    pub fn name(&self) -> &str {
+        &self.decl.id.name
+    }
 }
 
 impl Callable for LoxFunction {
@@ -94,12 +98,16 @@ impl Callable for LoxFunction {
             environment.define(param.name, arg);
         }
 
-        interpreter.scoped_statement(
+        interpreter.enter_call()?;
+        let result = interpreter.scoped_statement(
             |this| {
                 for stmt in self.decl.body.iter() {
                     match this.execute_stmt(stmt) {
                         Ok(_) => {}
                         Err(e @ ControlFlow::RuntimeError(_)) => return Err(e),
+                        Err(ControlFlow::Break | ControlFlow::Continue) => {
+                            unreachable!("loops can't be exited from within a function")
+                        }
                         Err(ControlFlow::Return(v)) => {
                             return match self.function_type {
                                 LoxFunctionType::Function => Ok(v),
@@ -120,7 +128,16 @@ impl Callable for LoxFunction {
                 }
             },
             environment,
-        )
+        );
+        interpreter.exit_call();
+
+        result.map_err(|e| match e {
+            ControlFlow::RuntimeError(mut e) => {
+                e.unwind(self.name());
+                ControlFlow::RuntimeError(e)
+            }
+            e => e,
+        })
     }
 }
 
@@ -0,0 +1,297 @@
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use crate::interpreter::error::{IndexOutOfRange, RResult, RuntimeError, TypeError};
+use crate::interpreter::CFResult;
+
+use super::Interpreter;
+use super::{Callable, RuntimeValue};
+
+/// Converts `index` to a position in a list of `len` items. When `inclusive` is set `len` itself
+/// is allowed, which is the position after the last item.
+pub fn to_position(index: &RuntimeValue, len: usize, inclusive: bool) -> RResult<usize> {
+    let n = match index {
+        RuntimeValue::Number(n) if n.fract() == 0.0 => *n,
+        index => {
+            return Err(RuntimeError::TypeError(TypeError {
+                message: format!("list indices must be integers, found {}", index).into(),
+            }))
+        }
+    };
+
+    let end = if inclusive { len + 1 } else { len };
+    if n >= 0.0 && n < end as f64 {
+        Ok(n as usize)
+    } else {
+        Err(RuntimeError::IndexOutOfRange(IndexOutOfRange {
+            message: format!("index {} is out of range for a list of length {}", n, len).into(),
+        }))
+    }
+}
+
+/// The native functions operating on lists, each takes the list as its first argument.
+#[derive(Debug, Copy, Clone)]
+pub enum ListNative {
+    /// `len(list)`, the number of items in the list.
+    Len,
+    /// `push(list, value)`, appends `value` to the end of the list.
+    Push,
+    /// `pop(list)`, removes and returns the last item of the list.
+    Pop,
+    /// `insert(list, index, value)`, inserts `value` before the item at `index`.
+    Insert,
+    /// `remove(list, index)`, removes and returns the item at `index`.
+    Remove,
+}
+
+impl ListNative {
+    pub const ALL: [ListNative; 5] = [
+        ListNative::Len,
+        ListNative::Push,
+        ListNative::Pop,
+        ListNative::Insert,
+        ListNative::Remove,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ListNative::Len => "len",
+            ListNative::Push => "push",
+            ListNative::Pop => "pop",
+            ListNative::Insert => "insert",
+            ListNative::Remove => "remove",
+        }
+    }
+
+    fn list(&self, value: &RuntimeValue) -> RResult<Rc<RefCell<Vec<RuntimeValue>>>> {
+        match value {
+            RuntimeValue::List(items) => Ok(items.clone()),
+            value => Err(RuntimeError::TypeError(TypeError {
+                message: format!("{}() expects a list, found {}", self.name(), value).into(),
+            })),
+        }
+    }
+}
+
+impl Callable for ListNative {
+    fn arity(&self) -> usize {
+        match self {
+            ListNative::Len | ListNative::Pop => 1,
+            ListNative::Push | ListNative::Remove => 2,
+            ListNative::Insert => 3,
+        }
+    }
+
+    fn call(
+        self: Rc<Self>,
+        _interpreter: &mut Interpreter,
+        mut args: Vec<RuntimeValue>,
+    ) -> CFResult<RuntimeValue> {
+        let list = self.list(&args[0])?;
+        // Positions are found before the list is borrowed mutably, as the error for an invalid
+        // index displays the index, which may be the list itself
+        let len = list.borrow().len();
+
+        let value = match *self {
+            ListNative::Len => RuntimeValue::Number(len as f64),
+            ListNative::Push => {
+                list.borrow_mut().push(args.pop().unwrap());
+                RuntimeValue::Nil
+            }
+            ListNative::Pop => list.borrow_mut().pop().ok_or_else(|| {
+                RuntimeError::IndexOutOfRange(IndexOutOfRange {
+                    message: "can't pop from an empty list".into(),
+                })
+            })?,
+            ListNative::Insert => {
+                let position = to_position(&args[1], len, true)?;
+                list.borrow_mut().insert(position, args.pop().unwrap());
+                RuntimeValue::Nil
+            }
+            ListNative::Remove => {
+                let position = to_position(&args[1], len, false)?;
+                list.borrow_mut().remove(position)
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+impl Display for ListNative {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lox_syntax::Parser;
+
+    use super::*;
+    use crate::Resolver;
+
+    /// Interprets `source` and returns the global `result` it defined, written as it would be
+    /// printed.
+    fn run(source: &str) -> Result<String, RuntimeError> {
+        let mut parser = Parser::new(source);
+        let stmts = parser.parse();
+        assert!(parser.diagnostics().is_empty());
+
+        let mut interpreter = Interpreter::new();
+        let mut resolver = Resolver::new(&mut interpreter);
+        resolver.resolve(&stmts);
+        assert!(resolver.diagnostics().is_empty());
+
+        interpreter.interpret(&stmts).map_err(|e| e.error)?;
+        match interpreter.globals.get("result") {
+            Ok(value) => Ok(value.to_string()),
+            Err(e) => panic!("`result` wasn't defined: {:?}", e),
+        }
+    }
+
+    fn type_error(source: &str) -> String {
+        match run(source) {
+            Err(e @ RuntimeError::TypeError(_)) => e.to_string(),
+            result => panic!("expected a type error, found {:?}", result),
+        }
+    }
+
+    fn out_of_range(source: &str) -> String {
+        match run(source) {
+            Err(e @ RuntimeError::IndexOutOfRange(_)) => e.to_string(),
+            result => panic!("expected an index out of range error, found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn indexes_lists() {
+        assert_eq!(run("var xs = [1, 2, 3]; var result = xs[2];").unwrap(), "3");
+        assert_eq!(
+            run("var xs = [1, 2, 3]; xs[0] = xs[1] = 5; var result = xs;").unwrap(),
+            "[5, 5, 3]"
+        );
+        // Lists are shared rather than copied when assigned
+        assert_eq!(
+            run("var xs = []; var ys = xs; push(ys, nil); var result = xs;").unwrap(),
+            "[nil]"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_indices() {
+        assert_eq!(
+            out_of_range("var result = [1, 2][2];"),
+            "index 2 is out of range for a list of length 2"
+        );
+        assert_eq!(
+            out_of_range("var result = [1, 2][-1];"),
+            "index -1 is out of range for a list of length 2"
+        );
+        assert_eq!(
+            out_of_range("var xs = []; xs[0] = 1;"),
+            "index 0 is out of range for a list of length 0"
+        );
+        assert_eq!(
+            type_error("var result = [1, 2][0.5];"),
+            "list indices must be integers, found 0.5"
+        );
+        assert_eq!(
+            type_error(r#"var xs = [1]; xs["0"] = 1;"#),
+            r#"list indices must be integers, found "0""#
+        );
+    }
+
+    #[test]
+    fn rejects_indexing_values_which_arent_lists() {
+        assert_eq!(
+            type_error("var n = 1; var result = n[0];"),
+            "only lists can be indexed"
+        );
+        assert_eq!(
+            type_error(r#"var s = "abc"; s[0] = "d";"#),
+            "only lists can be indexed"
+        );
+    }
+
+    #[test]
+    fn calls_list_natives() {
+        assert_eq!(run("var result = len([]);").unwrap(), "0");
+        assert_eq!(run("var result = len([1, [2, 3]]);").unwrap(), "2");
+        assert_eq!(
+            run("var xs = [1]; var result = [push(xs, 2), xs];").unwrap(),
+            "[nil, [1, 2]]"
+        );
+        assert_eq!(
+            run("var xs = [1, 2]; var result = [pop(xs), xs];").unwrap(),
+            "[2, [1]]"
+        );
+        assert_eq!(
+            run("var xs = [1, 3]; insert(xs, 1, 2); insert(xs, 0, 0); var result = xs;").unwrap(),
+            "[0, 1, 2, 3]"
+        );
+        assert_eq!(
+            run("var xs = [1, 2, 3]; var result = [remove(xs, 1), xs];").unwrap(),
+            "[2, [1, 3]]"
+        );
+    }
+
+    #[test]
+    fn inserts_at_the_end_of_a_list() {
+        assert_eq!(
+            run("var xs = [1, 2]; insert(xs, 2, 3); var result = xs;").unwrap(),
+            "[1, 2, 3]"
+        );
+        assert_eq!(
+            run("var xs = []; insert(xs, 0, 1); var result = xs;").unwrap(),
+            "[1]"
+        );
+        assert_eq!(
+            out_of_range("insert([1, 2], 3, 3);"),
+            "index 3 is out of range for a list of length 2"
+        );
+        assert_eq!(
+            out_of_range("insert([1, 2], -1, 3);"),
+            "index -1 is out of range for a list of length 2"
+        );
+    }
+
+    #[test]
+    fn rejects_removing_from_an_empty_list() {
+        assert_eq!(out_of_range("pop([]);"), "can't pop from an empty list");
+        assert_eq!(
+            out_of_range("remove([], 0);"),
+            "index 0 is out of range for a list of length 0"
+        );
+        assert_eq!(
+            type_error("remove([1], 0.5);"),
+            "list indices must be integers, found 0.5"
+        );
+    }
+
+    #[test]
+    fn rejects_lists_as_indices() {
+        assert_eq!(
+            type_error("var xs = [1]; remove(xs, xs);"),
+            "list indices must be integers, found [1]"
+        );
+        assert_eq!(
+            type_error("var xs = [1]; xs[xs] = 2;"),
+            "list indices must be integers, found [1]"
+        );
+        assert_eq!(
+            type_error("var xs = [1]; insert(xs, [xs], 2);"),
+            "list indices must be integers, found [[1]]"
+        );
+    }
+
+    #[test]
+    fn rejects_natives_called_without_a_list() {
+        assert_eq!(type_error("len(nil);"), "len() expects a list, found nil");
+        assert_eq!(
+            type_error(r#"push("abc", 1);"#),
+            r#"push() expects a list, found "abc""#
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::rc::Rc;
 use std::{
     fmt::{Debug, Display, Formatter},
@@ -11,6 +12,7 @@ use crate::Interpreter;
 
 pub mod class;
 pub mod function;
+pub mod list;
 
 use class::{Class, Instance};
 
@@ -23,6 +25,8 @@ pub enum RuntimeValue {
     Function(Rc<dyn Callable>),
     Class(Rc<Class>),
     Object(Rc<Instance>),
+    /// A list, shared by every value it's copied to.
+    List(Rc<RefCell<Vec<RuntimeValue>>>),
 }
 
 impl RuntimeValue {
@@ -59,10 +63,38 @@ impl Display for RuntimeValue {
             Function(fun) => write!(f, "{}", fun),
             Class(class) => write!(f, "{}", class),
             Object(instance) => write!(f, "{}", instance),
+            List(list) => fmt_list(list, &mut Vec::new(), f),
         }
     }
 }
 
+/// Writes `list` and its items, `enclosing` holds the lists being written by the calls this is
+/// nested in and any of them which is reached again is written as `[...]` so that lists which
+/// contain themselves can be displayed.
+fn fmt_list(
+    list: &Rc<RefCell<Vec<RuntimeValue>>>,
+    enclosing: &mut Vec<*const RefCell<Vec<RuntimeValue>>>,
+    f: &mut Formatter<'_>,
+) -> std::fmt::Result {
+    if enclosing.contains(&Rc::as_ptr(list)) {
+        return f.write_str("[...]");
+    }
+
+    enclosing.push(Rc::as_ptr(list));
+    f.write_str("[")?;
+    for (i, item) in list.borrow().iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        match item {
+            RuntimeValue::List(inner) => fmt_list(inner, enclosing, f)?,
+            item => write!(f, "{}", item)?,
+        }
+    }
+    enclosing.pop();
+    f.write_str("]")
+}
+
 impl PartialEq for RuntimeValue {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -75,6 +107,7 @@ impl PartialEq for RuntimeValue {
             ),
             (Self::Class(l), Self::Class(r)) => Rc::ptr_eq(l, r),
             (Self::Object(l), Self::Object(r)) => Rc::ptr_eq(l, r),
+            (Self::List(l), Self::List(r)) => Rc::ptr_eq(l, r),
             _ => mem::discriminant(self) == mem::discriminant(other),
         }
     }
@@ -88,3 +121,36 @@ pub trait Callable: Debug + Display {
         args: Vec<RuntimeValue>,
     ) -> CFResult<RuntimeValue>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: Vec<RuntimeValue>) -> RuntimeValue {
+        RuntimeValue::List(Rc::new(RefCell::new(items)))
+    }
+
+    #[test]
+    fn displays_lists_which_contain_themselves() {
+        let RuntimeValue::List(outer) = list(vec![RuntimeValue::Number(1.0)]) else {
+            unreachable!()
+        };
+        let inner = list(vec![RuntimeValue::List(outer.clone())]);
+        outer.borrow_mut().push(RuntimeValue::List(outer.clone()));
+        outer.borrow_mut().push(inner);
+
+        assert_eq!(
+            RuntimeValue::List(outer.clone()).to_string(),
+            "[1, [...], [[...]]]"
+        );
+
+        // A list which is repeated without containing itself is written in full
+        let shared = list(vec![RuntimeValue::Nil]);
+        assert_eq!(
+            list(vec![shared.clone(), shared]).to_string(),
+            "[[nil], [nil]]"
+        );
+        // Break the cycle so the list can be dropped
+        outer.borrow_mut().clear();
+    }
+}
@@ -1,4 +1,4 @@
-pub use interpreter::Interpreter;
+pub use interpreter::{Interpreter, RuntimeError, TraceFrame, TracedError, DEFAULT_MAX_CALL_DEPTH};
 pub use resolver::Resolver;
 
 mod interpreter;
@@ -3,6 +3,7 @@ use std::{
     fmt::{Display, Formatter},
 };
 
+use lox_syntax::diagnostic::Diagnostic;
 use lox_syntax::span::Span;
 
 #[derive(Debug)]
@@ -33,6 +34,46 @@ pub enum ResolverError {
         span: Span,
         message: Cow<'static, str>,
     },
+    OutsideLoop {
+        span: Span,
+        message: Cow<'static, str>,
+    },
+}
+
+impl ResolverError {
+    pub fn span(&self) -> Span {
+        use ResolverError::*;
+
+        match self {
+            InitializeFromSelf { span }
+            | AlreadyDeclared { span }
+            | Undeclared { span, .. }
+            | ReturnOutsideFn { span }
+            | ThisOutsideClass { span }
+            | ReturnValueFromInit { span }
+            | InheritFromSelf { span }
+            | InvalidSuper { span, .. }
+            | OutsideLoop { span, .. } => *span,
+        }
+    }
+
+    /// The stable code identifying the kind of error, shared with the equivalent errors reported
+    /// by the bytecode compiler.
+    pub fn code(&self) -> &'static str {
+        use ResolverError::*;
+
+        match self {
+            InitializeFromSelf { .. } => "E0101",
+            AlreadyDeclared { .. } => "E0102",
+            Undeclared { .. } => "E0103",
+            ReturnOutsideFn { .. } => "E0104",
+            ThisOutsideClass { .. } => "E0105",
+            ReturnValueFromInit { .. } => "E0106",
+            InheritFromSelf { .. } => "E0107",
+            InvalidSuper { .. } => "E0108",
+            OutsideLoop { .. } => "E0109",
+        }
+    }
 }
 
 impl Display for ResolverError {
@@ -48,6 +89,25 @@ impl Display for ResolverError {
             ReturnValueFromInit { .. } => f.write_str("can't return a value inside `init` method"),
             InheritFromSelf { .. } => f.write_str("a class can't inherit from itself"),
             InvalidSuper { message, .. } => f.write_str(message),
+            OutsideLoop { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl From<&ResolverError> for Diagnostic {
+    fn from(error: &ResolverError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string())
+            .with_code(error.code())
+            .with_span(error.span());
+
+        match error {
+            ResolverError::InitializeFromSelf { .. } => diagnostic
+                .with_note("a local variable can't be read in its own initializer")
+                .with_help("give the variable a different name to read the outer variable"),
+            ResolverError::ReturnValueFromInit { .. } => {
+                diagnostic.with_note("initializers always return the instance being initialized")
+            }
+            _ => diagnostic,
         }
     }
 }
@@ -1,6 +1,8 @@
-use lox_syntax::ast::expr::{Assign, Binary, Call, Expr, Logical, Set, Super, This, Var};
+use lox_syntax::ast::expr::{
+    Assign, Binary, Call, Expr, Index, Logical, Set, SetIndex, Super, This, Var,
+};
 
-use super::{BindingState, ClassType, Resolver, ResolverError};
+use super::{BindingState, ClassType, FunctionType, Resolver, ResolverError};
 
 impl Resolver<'_> {
     pub(super) fn resolve_expr(&mut self, expr: &Expr) {
@@ -17,6 +19,14 @@ impl Resolver<'_> {
             Expr::Set(s) => self.resolve_set_expr(s),
             Expr::This(t) => self.resolve_this_expr(t),
             Expr::Super(s) => self.resolve_super_expr(s),
+            Expr::Lambda(l) => self.resolve_function(&l.decl, FunctionType::Function),
+            Expr::List(l) => {
+                for item in l.items.iter() {
+                    self.resolve_expr(item);
+                }
+            }
+            Expr::Index(i) => self.resolve_index_expr(i),
+            Expr::SetIndex(s) => self.resolve_set_index_expr(s),
         }
     }
 
@@ -66,6 +76,17 @@ impl Resolver<'_> {
         self.resolve_expr(&set.value);
     }
 
+    fn resolve_index_expr(&mut self, index: &Index) {
+        self.resolve_expr(&index.object);
+        self.resolve_expr(&index.index);
+    }
+
+    fn resolve_set_index_expr(&mut self, set: &SetIndex) {
+        self.resolve_expr(&set.object);
+        self.resolve_expr(&set.index);
+        self.resolve_expr(&set.value);
+    }
+
     fn resolve_super_expr(&mut self, super_expr: &Super) {
         match self.class_type {
             ClassType::None => {
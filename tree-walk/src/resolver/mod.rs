@@ -21,6 +21,8 @@ pub struct Resolver<'a> {
     diagnostics: Vec<ResolverError>,
     function_type: FunctionType,
     class_type: ClassType,
+    /// The number of loops enclosing the statement being resolved within the current function.
+    loop_depth: usize,
 }
 
 #[derive(Debug)]
@@ -52,6 +54,7 @@ impl<'a> Resolver<'a> {
             diagnostics: Vec::new(),
             function_type: FunctionType::None,
             class_type: ClassType::None,
+            loop_depth: 0,
         }
     }
 
@@ -119,9 +122,12 @@ impl<'a> Resolver<'a> {
         F: FnOnce(&mut Self),
     {
         let restore = self.function_type;
+        // Loops don't extend into the functions declared within them
+        let loop_depth = std::mem::take(&mut self.loop_depth);
         self.function_type = function_type;
         self.scoped(f);
         self.function_type = restore;
+        self.loop_depth = loop_depth;
     }
 
     fn error(&mut self, error: ResolverError) {
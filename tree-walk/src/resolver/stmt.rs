@@ -1,6 +1,8 @@
 use lox_syntax::ast::{
     expr::{Expr, Literal, Value},
-    stmt::{Block, ClassDecl, ExprStmt, FunDecl, If, Print, Return, Stmt, Var, While},
+    stmt::{
+        Block, Break, ClassDecl, Continue, ExprStmt, FunDecl, If, Print, Return, Stmt, Var, While,
+    },
 };
 
 use super::{ClassType, FunctionType, Resolver, ResolverError};
@@ -13,6 +15,8 @@ impl Resolver<'_> {
             Stmt::Expr(e) => self.resolve_expr_stmt(e),
             Stmt::Block(b) => self.resolve_block_stmt(b),
             Stmt::While(w) => self.resolve_while_stmt(w),
+            Stmt::Break(b) => self.resolve_break_stmt(b),
+            Stmt::Continue(c) => self.resolve_continue_stmt(c),
             Stmt::Print(p) => self.resolve_print_stmt(p),
             Stmt::If(i) => self.resolve_if_stmt(i),
             Stmt::Return(r) => self.resolve_return_stmt(r),
@@ -29,6 +33,11 @@ impl Resolver<'_> {
     fn resolve_fun_decl(&mut self, fun_decl: &FunDecl) {
         self.declare(&fun_decl.id);
         self.define(&fun_decl.id);
+        self.resolve_function(fun_decl, FunctionType::Function);
+    }
+
+    /// Resolves the parameters and body of a function in a new function scope.
+    pub(super) fn resolve_function(&mut self, fun_decl: &FunDecl, function_type: FunctionType) {
         self.scoped_fn(
             |this| {
                 for param in fun_decl.params.iter() {
@@ -38,7 +47,7 @@ impl Resolver<'_> {
 
                 this.resolve(&fun_decl.body);
             },
-            FunctionType::Function,
+            function_type,
         );
     }
 
@@ -80,17 +89,7 @@ impl Resolver<'_> {
                     FunctionType::Method
                 };
 
-                this.scoped_fn(
-                    |that| {
-                        for param in method.params.iter() {
-                            that.declare(param);
-                            that.define(param);
-                        }
-
-                        that.resolve(&method.body);
-                    },
-                    function_type,
-                )
+                this.resolve_function(method, function_type);
             }
             this.class_type = restore;
         });
@@ -123,18 +122,18 @@ impl Resolver<'_> {
                     span: return_stmt.span,
                 });
             }
-            FunctionType::Initializer => {
+            FunctionType::Initializer
                 if !matches!(
                     return_stmt.expr,
                     Expr::Literal(Literal {
                         value: Value::Nil,
                         ..
                     }),
-                ) {
-                    self.error(ResolverError::ReturnValueFromInit {
-                        span: return_stmt.span,
-                    });
-                }
+                ) =>
+            {
+                self.error(ResolverError::ReturnValueFromInit {
+                    span: return_stmt.span,
+                });
             }
             _ => {}
         }
@@ -143,7 +142,30 @@ impl Resolver<'_> {
 
     fn resolve_while_stmt(&mut self, while_stmt: &While) {
         self.resolve_expr(&while_stmt.cond);
+        self.loop_depth += 1;
         self.resolve_stmt(&while_stmt.stmt);
+        self.loop_depth -= 1;
+        if let Some(increment) = &while_stmt.increment {
+            self.resolve_expr(increment);
+        }
+    }
+
+    fn resolve_break_stmt(&mut self, break_stmt: &Break) {
+        if self.loop_depth == 0 {
+            self.error(ResolverError::OutsideLoop {
+                span: break_stmt.span,
+                message: "can't use `break` outside of a loop".into(),
+            });
+        }
+    }
+
+    fn resolve_continue_stmt(&mut self, continue_stmt: &Continue) {
+        if self.loop_depth == 0 {
+            self.error(ResolverError::OutsideLoop {
+                span: continue_stmt.span,
+                message: "can't use `continue` outside of a loop".into(),
+            });
+        }
     }
 
     fn resolve_block_stmt(&mut self, block_stmt: &Block) {